@@ -1,5 +1,7 @@
 //! Clock configuration
 use crate::core::clint::MTIME;
+use crate::flash::QspiFlash;
+use crate::spi::closest_divisor;
 use crate::time::Hertz;
 use e310x::{AONCLK, PRCI};
 use riscv::interrupt;
@@ -15,6 +17,20 @@ const PLLOUT_MIN: u32 = 48_000_000;
 const PLLOUT_MAX: u32 = 384_000_000;
 const DIVOUT_MIN: u32 = 375_000;
 const DIVOUT_MAX: u32 = 384_000_000;
+/// Nominal `HFROSC` frequency before its divider, for the default trim value
+const HFROSC_BASE: u32 = 69_000_000;
+/// `HFROSC` trim value used when freezing the clocks
+const HFROSC_TRIM_DEFAULT: u8 = 16;
+/// Nominal `HFROSC` frequency after the divider set when freezing the clocks
+const HFROSC_DEFAULT: u32 = HFROSC_BASE / 5;
+/// Largest coreclk ratio between two steps of [`Clocks::ramp_coreclk`]
+const RAMP_STEP_RATIO: u32 = 2;
+/// Largest `HFROSC` trim value
+const HFROSC_TRIM_MAX: u8 = 31;
+/// `mtime` ticks measured per trim value when calibrating `HFROSC`
+const HFROSC_CAL_TICKS: u64 = 64;
+/// `HFXOSC` frequency assumed by [`measured_coreclk`]
+const HFXOSC_DEFAULT: u32 = 16_000_000;
 
 /// PrciExt trait extends `PRCI` peripheral.
 pub trait PrciExt {
@@ -53,6 +69,10 @@ pub struct CoreClk {
 
 impl CoreClk {
     /// Uses `HFXOSC` (external oscillator) instead of `HFROSC` (internal ring oscillator) as the clock source.
+    ///
+    /// `HFXOSC` can either be a crystal between `XIN` and `XOUT` or an external
+    /// oscillator (e.g. a TCXO) driving `XIN`. The `PRCI` has no bypass setting,
+    /// both cases are configured the same way.
     pub fn use_external<F: Into<Hertz>>(mut self, freq: F) -> Self {
         let hz: Hertz = freq.into();
         assert!(hz.0 < 20_000_000);
@@ -69,11 +89,17 @@ impl CoreClk {
 
     /// Freezes high-frequency clock configuration, making it effective
     pub(crate) fn freeze(self) -> Hertz {
+        self.freeze_with_trim(HFROSC_TRIM_DEFAULT, Hertz(HFROSC_DEFAULT))
+    }
+
+    /// Freezes high-frequency clock configuration, running `HFROSC` with `trim`,
+    /// at which it runs at `hfrosc_freq`
+    fn freeze_with_trim(self, trim: u8, hfrosc_freq: Hertz) -> Hertz {
         // Assume `psdclkbypass_n` is not used
 
         // Temporarily switch to the internal oscillator
         let prci = unsafe { &*PRCI::ptr() };
-        let hfrosc_freq = self.configure_hfrosc();
+        self.configure_hfrosc(trim);
         // Switch to HFROSC, bypass PLL
         prci.pllcfg
             .modify(|_, w| w.sel().bit(false).bypass().bit(true));
@@ -118,8 +144,8 @@ impl CoreClk {
         // Switch to PLL
         prci.pllcfg.modify(|_, w| w.sel().bit(true));
 
-        // Disable HFROSC to save power
-        prci.hfrosccfg.write(|w| w.enable().bit(false));
+        // Disable HFROSC to save power, keeping its trim
+        prci.hfrosccfg.modify(|_, w| w.enable().bit(false));
 
         freq
     }
@@ -156,19 +182,17 @@ impl CoreClk {
     }
 
     /// Configures internal high-frequency oscillator (`HFROSC`)
-    fn configure_hfrosc(&self) -> Hertz {
+    fn configure_hfrosc(&self, trim: u8) {
         let prci = unsafe { &*PRCI::ptr() };
 
         // TODO: use trim value from OTP
 
-        // Configure HFROSC to 13.8 MHz
+        // Configure HFROSC to 13.8 MHz, for the default trim value
         prci.hfrosccfg
-            .write(|w| unsafe { w.div().bits(4).trim().bits(16).enable().bit(true) });
+            .write(|w| unsafe { w.div().bits(4).trim().bits(trim).enable().bit(true) });
 
         // Wait for HFROSC to stabilize
         while !prci.hfrosccfg.read().ready().bit_is_set() {}
-
-        Hertz(13_800_000)
     }
 
     /// Configures PLL and PLL Output Divider
@@ -308,6 +332,9 @@ pub struct AonClk {
 
 impl AonClk {
     /// Uses `LFALTCLK` (external low-frequency clock) instead of `LFROSC` (internal ring oscillator) as the clock source.
+    ///
+    /// `LFALTCLK` is a clock input driven by an external source, e.g. a 32768 Hz
+    /// oscillator, so it needs no bypass setting either.
     pub fn use_external<F: Into<Hertz>>(mut self, freq: F) -> Self {
         let hz: Hertz = freq.into();
         assert!(hz.0 < 500_000);
@@ -356,14 +383,23 @@ impl AonClk {
 pub struct Clocks {
     coreclk: Hertz,
     lfclk: Hertz,
+    hfxosc: Option<Hertz>,
+    /// `HFROSC` frequency after its divider, measured by [calibrate_hfrosc](Self::calibrate_hfrosc)
+    hfrosc: Hertz,
 }
 
 impl Clocks {
     /// Freezes the coreclk and aonclk frequencies.
     pub fn freeze(coreclk: CoreClk, aonclk: AonClk) -> Self {
+        let hfxosc = coreclk.hfxosc;
         let coreclk = coreclk.freeze();
         let lfclk = aonclk.freeze();
-        Clocks { coreclk, lfclk }
+        Clocks {
+            coreclk,
+            lfclk,
+            hfxosc,
+            hfrosc: Hertz(HFROSC_DEFAULT),
+        }
     }
 
     /// Returns the frozen coreclk frequency
@@ -383,6 +419,77 @@ impl Clocks {
         self.lfclk
     }
 
+    /// Changes the coreclk frequency to `target`, keeping the QSPI0 flash clock at
+    /// or below `flash_sck_max` all along
+    ///
+    /// The frequency is changed in steps of at most a factor of 2. The PLL can't be
+    /// retuned while it drives coreclk, so at each step coreclk runs from `HFROSC`
+    /// while the PLL is reprogrammed and waits for it to lock, then moves to the
+    /// new setting. The `HFROSC` trim, e.g. from
+    /// [calibrate_hfrosc](Self::calibrate_hfrosc), is kept, and the PLL uses the
+    /// calibrated frequency as its reference.
+    ///
+    /// The FE310 has no flash wait states: the XIP flash timing only depends on the
+    /// QSPI0 SCK frequency, `tlclk / (2 * (sckdiv + 1))`. Before each step,
+    /// `sckdiv` is raised to suit the current frequency, `HFROSC` and the step
+    /// target plus the 2% the PLL may exceed it by. Afterwards it is set to the
+    /// fastest value allowed for the achieved frequency.
+    ///
+    /// Returns the new coreclk frequency. Peripherals configured with the previous
+    /// [Clocks] must be reclocked afterwards.
+    pub fn ramp_coreclk(
+        &mut self,
+        flash: &mut QspiFlash,
+        target: Hertz,
+        flash_sck_max: Hertz,
+    ) -> Hertz {
+        let prci = unsafe { &*PRCI::ptr() };
+        let hfrosccfg = prci.hfrosccfg.read();
+        let trim = if hfrosccfg.enable().bit_is_set() {
+            hfrosccfg.trim().bits()
+        } else {
+            HFROSC_TRIM_DEFAULT
+        };
+
+        let mut step = self.coreclk.0;
+        loop {
+            step = if target.0 > step {
+                target.0.min(step.saturating_mul(RAMP_STEP_RATIO))
+            } else {
+                target.0.max(step / RAMP_STEP_RATIO)
+            };
+            self.step_coreclk(flash, Hertz(step), flash_sck_max, trim);
+
+            if step == target.0 {
+                return self.coreclk;
+            }
+        }
+    }
+
+    /// Switches coreclk to `target` through `HFROSC`, see [ramp_coreclk](Self::ramp_coreclk)
+    fn step_coreclk(
+        &mut self,
+        flash: &mut QspiFlash,
+        target: Hertz,
+        flash_sck_max: Hertz,
+        trim: u8,
+    ) {
+        let fastest = (target.0 as u64 * 102 / 100) as u32;
+        let fastest = fastest.max(self.coreclk.0).max(self.hfrosc.0);
+        let div = closest_divisor(Hertz(fastest), flash_sck_max) as u16;
+        if div > flash.sck_div() {
+            flash.set_sck_div(div);
+        }
+
+        let coreclk = CoreClk {
+            hfxosc: self.hfxosc,
+            coreclk: target,
+        };
+        self.coreclk = coreclk.freeze_with_trim(trim, self.hfrosc);
+
+        flash.set_sck_div(closest_divisor(self.coreclk, flash_sck_max) as u16);
+    }
+
     /// Measure the coreclk frequency by counting the number of aonclk ticks.
     fn _measure_coreclk(&self, min_ticks: u64) -> Hertz {
         let mtime = MTIME;
@@ -409,6 +516,59 @@ impl Clocks {
         })
     }
 
+    /// Trims `HFROSC` to get as close as possible to `target` and returns the
+    /// achieved frequency, which also becomes the frozen coreclk frequency
+    ///
+    /// `HFROSC` is measured against `mtime` for every trim value tried, so the
+    /// result is only as accurate as `lfclk`: use a 32768 Hz crystal as `LFALTCLK`
+    /// (see [AonClk::use_external]) rather than the internal `LFROSC`. `target`
+    /// refers to the frequency after the `HFROSC` divider.
+    ///
+    /// Peripherals configured from the previous frequency (e.g. baud rates) must
+    /// be reconfigured afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if coreclk is not driven directly by `HFROSC`.
+    pub fn calibrate_hfrosc(&mut self, target: Hertz) -> Hertz {
+        let prci = unsafe { &*PRCI::ptr() };
+        assert!(!prci.pllcfg.read().sel().bit_is_set());
+
+        let measure = |trim: u8| {
+            prci.hfrosccfg.modify(|_, w| unsafe { w.trim().bits(trim) });
+            while !prci.hfrosccfg.read().ready().bit_is_set() {}
+            self._measure_coreclk(HFROSC_CAL_TICKS).0
+        };
+
+        // HFROSC frequency increases with the trim value
+        let (mut lo, mut hi) = (0, HFROSC_TRIM_MAX);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if measure(mid) < target.0 {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // The closest frequency is either right above or right below the target
+        let mut trim = lo;
+        let mut freq = measure(trim);
+        if trim > 0 {
+            let below = measure(trim - 1);
+            let error = |f: u32| (target.0 as i64 - f as i64).abs();
+            if error(below) < error(freq) {
+                trim -= 1;
+                freq = below;
+            }
+        }
+        measure(trim);
+
+        self.coreclk = Hertz(freq);
+        self.hfrosc = self.coreclk;
+        self.coreclk
+    }
+
     /// Measure the coreclk frequency by counting the number of aonclk ticks.
     pub fn measure_coreclk(&self) -> Hertz {
         // warm up I$
@@ -417,3 +577,51 @@ impl Clocks {
         self._measure_coreclk(10)
     }
 }
+
+/// Computes the current coreclk frequency from the live `PRCI` configuration
+///
+/// Unlike [`Clocks::coreclk`], this decodes the PLL, PLL output divider and
+/// oscillator selection registers, so it reflects changes made after the clocks
+/// were frozen (or by the boot ROM). The `HFXOSC` crystal frequency can't be read
+/// from the hardware and is assumed to be 16 MHz, as on the HiFive1 boards; use
+/// [`measured_coreclk_with`] for other crystals. The `HFROSC` frequency is a
+/// nominal value which ignores the trim setting.
+pub fn measured_coreclk() -> Hertz {
+    measured_coreclk_with(Hertz(HFXOSC_DEFAULT))
+}
+
+/// Computes the current coreclk frequency from the live `PRCI` configuration
+/// using the given `HFXOSC` frequency (see [`measured_coreclk`])
+pub fn measured_coreclk_with(hfxosc: Hertz) -> Hertz {
+    let prci = unsafe { &*PRCI::ptr() };
+
+    let hfrosc = HFROSC_BASE / (prci.hfrosccfg.read().div().bits() as u32 + 1);
+
+    let pllcfg = prci.pllcfg.read();
+    if !pllcfg.sel().bit_is_set() {
+        // PLL path not selected, coreclk is driven by HFROSC
+        return Hertz(hfrosc);
+    }
+
+    let pllref = if pllcfg.refsel().bit_is_set() {
+        hfxosc.0
+    } else {
+        hfrosc
+    };
+
+    let pllout = if pllcfg.bypass().bit_is_set() {
+        pllref
+    } else {
+        let r = pllcfg.pllr().bits() as u64 + 1;
+        let f = 2 * (pllcfg.pllf().bits() as u64 + 1);
+        let q = 1 << pllcfg.pllq().bits();
+        (pllref as u64 * f / (r * q)) as u32
+    };
+
+    let plloutdiv = prci.plloutdiv.read();
+    if plloutdiv.divby1().bit_is_set() {
+        Hertz(pllout)
+    } else {
+        Hertz(pllout / (2 * (plloutdiv.div().bits() as u32 + 1)))
+    }
+}
@@ -1,6 +1,8 @@
 //! Core-Local Interruptor
 
+use super::guard::InterruptGuard;
 use e310x::CLINT;
+use riscv::register::{mie, mip};
 
 macro_rules! read64 {
     ($hi:expr, $lo:expr) => {
@@ -15,6 +17,8 @@ macro_rules! read64 {
 }
 
 /// Opaque msip register
+///
+/// The FE310 has a single hart, so this is the `msip` register of hart 0.
 pub struct MSIP {
     _0: (),
 }
@@ -28,6 +32,24 @@ impl MSIP {
                 .write(|w| if value { w.bits(1) } else { w.bits(0) })
         }
     }
+
+    /// Enable MachineSoft interrupt.
+    #[inline]
+    pub fn listen(&mut self) {
+        unsafe { mie::set_msoft() };
+    }
+
+    /// Disable MachineSoft interrupt.
+    #[inline]
+    pub fn unlisten(&mut self) {
+        unsafe { mie::clear_msoft() };
+    }
+
+    /// Returns true when MachineSoft interrupt is pending.
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        mip::read().msoft()
+    }
 }
 
 /// Opaque mtime register
@@ -106,6 +128,47 @@ pub struct Clint {
     pub mtime: MTIME,
 }
 
+impl Clint {
+    /// Enables the machine timer interrupt until the returned guard is dropped
+    pub fn timer_interrupt_enabled(&self) -> InterruptGuard {
+        InterruptGuard::timer()
+    }
+
+    /// Enables the machine software interrupt until the returned guard is dropped
+    pub fn software_interrupt_enabled(&self) -> InterruptGuard {
+        InterruptGuard::software()
+    }
+
+    /// Raises the machine software interrupt of hart 0
+    ///
+    /// An interrupt handler can use this to defer work to the `MachineSoft`
+    /// handler, which must call [Clint::clear_msip] to acknowledge it.
+    pub fn set_msip(&mut self) {
+        self.msip.set_value(true);
+    }
+
+    /// Clears the machine software interrupt of hart 0
+    pub fn clear_msip(&mut self) {
+        self.msip.set_value(false);
+    }
+
+    /// Writes the mtime and mtimeh registers
+    ///
+    /// # Safety
+    ///
+    /// Every mtime based delay, timeout and timer interrupt in the program is
+    /// skewed: moving the time backwards postpones pending `mtimecmp` deadlines,
+    /// moving it forwards expires them early. The caller must ensure that no
+    /// such wait is in progress, or that skewing it is acceptable.
+    pub unsafe fn set_time(&mut self, value: u64) {
+        let clint = &*CLINT::ptr();
+        // Clear the low word first, so that it can't carry into mtimeh
+        clint.mtime.write(|w| w.bits(0));
+        clint.mtimeh.write(|w| w.bits((value >> 32) as u32));
+        clint.mtime.write(|w| w.bits(value as u32));
+    }
+}
+
 impl From<CLINT> for Clint {
     fn from(_: CLINT) -> Self {
         Clint {
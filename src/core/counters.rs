@@ -1,7 +1,22 @@
 //! Performance counters
+//!
+//! The counters keep counting in machine mode, which is where all code runs on
+//! the FE310, and wrap around at 64 bits.
 
 use riscv::register::{mcycle, mhpmcounter3, mhpmcounter4, minstret};
 
+/// Returns the number of clock cycles executed by the core, see [MCYCLE]
+#[inline]
+pub fn cycle_count() -> u64 {
+    mcycle::read64()
+}
+
+/// Returns the number of instructions retired by the core, see [MINSTRET]
+#[inline]
+pub fn instret_count() -> u64 {
+    minstret::read64()
+}
+
 /// Opaque mcycle register
 pub struct MCYCLE;
 
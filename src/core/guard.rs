@@ -0,0 +1,82 @@
+//! Interrupt enable guards
+
+use riscv::register::mie;
+
+/// Machine interrupt sources with an enable bit in `mie`
+#[derive(Clone, Copy)]
+enum Source {
+    Timer,
+    Software,
+    External,
+}
+
+impl Source {
+    fn is_enabled(self) -> bool {
+        let mie = mie::read();
+        match self {
+            Source::Timer => mie.mtimer(),
+            Source::Software => mie.msoft(),
+            Source::External => mie.mext(),
+        }
+    }
+
+    unsafe fn set(self) {
+        match self {
+            Source::Timer => mie::set_mtimer(),
+            Source::Software => mie::set_msoft(),
+            Source::External => mie::set_mext(),
+        }
+    }
+
+    unsafe fn clear(self) {
+        match self {
+            Source::Timer => mie::clear_mtimer(),
+            Source::Software => mie::clear_msoft(),
+            Source::External => mie::clear_mext(),
+        }
+    }
+}
+
+/// Keeps a machine interrupt source enabled while alive.
+///
+/// The previous state of the source is restored on drop, so a source
+/// that was already enabled stays enabled.
+#[must_use = "the interrupt is disabled again when the guard is dropped"]
+pub struct InterruptGuard {
+    source: Source,
+    was_enabled: bool,
+}
+
+impl InterruptGuard {
+    fn new(source: Source) -> Self {
+        let was_enabled = source.is_enabled();
+        unsafe { source.set() };
+        InterruptGuard {
+            source,
+            was_enabled,
+        }
+    }
+
+    /// Enables the machine timer interrupt
+    pub(crate) fn timer() -> Self {
+        Self::new(Source::Timer)
+    }
+
+    /// Enables the machine software interrupt
+    pub(crate) fn software() -> Self {
+        Self::new(Source::Software)
+    }
+
+    /// Enables the machine external interrupt
+    pub(crate) fn external() -> Self {
+        Self::new(Source::External)
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if !self.was_enabled {
+            unsafe { self.source.clear() };
+        }
+    }
+}
@@ -1,9 +1,20 @@
 //! E31 core peripherals
+//!
+//! The types commonly needed to configure interrupts are re-exported here,
+//! so that `use e310x_hal::core::*` brings the [`Plic`] and [`Clint`] handles,
+//! the PLIC [`Priority`] levels and the [`Interrupt`] sources into scope.
 
 pub mod clint;
 pub mod counters;
+pub mod guard;
 pub mod plic;
 
+pub use clint::Clint;
+pub use counters::PerformanceCounters;
+pub use e310x::Interrupt;
+pub use guard::InterruptGuard;
+pub use plic::{Plic, Priority};
+
 /// Core peripherals
 pub struct CorePeripherals {
     /// Core-Local Interruptor
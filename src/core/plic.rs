@@ -1,11 +1,12 @@
 //! Platform-Level Interrupt Controller
+use super::guard::InterruptGuard;
 use core::marker::PhantomData;
 use e310x::Interrupt;
 use e310x::PLIC;
 use riscv::register::{mie, mip};
 
 /// Priority of a plic::Interrupt.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Priority {
     /// Priority 0: Never interrupt
     P0,
@@ -83,6 +84,65 @@ pub struct Plic {
     pub uart0: INTERRUPT<IrqUart0>,
 }
 
+impl Plic {
+    /// Enables the machine external interrupt until the returned guard is dropped
+    pub fn external_interrupt_enabled(&self) -> InterruptGuard {
+        InterruptGuard::external()
+    }
+
+    /// Claims the pending external interrupt with the highest priority
+    ///
+    /// Claiming clears the pending bit of the source. The PLIC does not
+    /// signal the same source again until it is passed to [Plic::complete],
+    /// so every claimed interrupt must be completed once it is handled:
+    ///
+    /// ```ignore
+    /// while let Some(source) = plic.next_pending() {
+    ///     dispatch(source);
+    ///     plic.complete(source);
+    /// }
+    /// ```
+    pub fn next_pending(&mut self) -> Option<Interrupt> {
+        self.claim.claim()
+    }
+
+    /// Sets the priority of any interrupt source
+    pub fn set_priority(&mut self, intr: Interrupt, priority: Priority) {
+        // NOTE: Atomic write without side effects.
+        unsafe {
+            (*PLIC::ptr()).priority[intr as usize].write(|w| w.bits(priority.into()));
+        }
+    }
+
+    /// Sets the priority threshold, masking sources with a priority at or below it
+    pub fn set_threshold(&mut self, priority: Priority) {
+        self.threshold.set(priority);
+    }
+
+    /// Enables any interrupt source in the PLIC
+    pub fn enable_interrupt(&mut self, intr: Interrupt) {
+        let (offset, mask) = (intr as usize / 32, 1 << (intr as u32 % 32));
+        // NOTE: should use atomic operations
+        unsafe {
+            (*PLIC::ptr()).enable[offset].modify(|r, w| w.bits(r.bits() | mask));
+        }
+    }
+
+    /// Disables any interrupt source in the PLIC
+    pub fn disable_interrupt(&mut self, intr: Interrupt) {
+        let (offset, mask) = (intr as usize / 32, 1 << (intr as u32 % 32));
+        // NOTE: should use atomic operations
+        unsafe {
+            (*PLIC::ptr()).enable[offset].modify(|r, w| w.bits(r.bits() & !mask));
+        }
+    }
+
+    /// Completes an interrupt returned by [Plic::next_pending]
+    pub fn complete(&mut self, intr: Interrupt) {
+        self.claim.complete(intr);
+    }
+}
+
 impl From<PLIC> for Plic {
     fn from(_: PLIC) -> Self {
         Plic {
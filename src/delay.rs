@@ -2,29 +2,76 @@
 
 use crate::clock::Clocks;
 use crate::core::clint::{MTIME, MTIMECMP};
+use crate::core::InterruptGuard;
+use crate::time::{duration_to_ticks, units_to_ticks, Microseconds, MTIME_TICKS_PER_SECOND};
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
-use riscv::register::{mie, mip};
+use riscv::interrupt;
+#[cfg(feature = "critical-section")]
+use riscv::register::mie;
+use riscv::register::mip;
 
 /// Machine timer (mtime) as a busyloop delay provider
 pub struct Delay;
 
-const TICKS_PER_SECOND: u64 = 32768;
+const TICKS_PER_SECOND: u64 = MTIME_TICKS_PER_SECOND;
 
 impl Delay {
     /// Constructs a delay provider based on the machine timer (mtime)
     pub fn new() -> Self {
         Delay
     }
-}
-
-impl DelayUs<u32> for Delay {
-    fn delay_us(&mut self, us: u32) {
-        let ticks = (us as u64) * TICKS_PER_SECOND / 1_000_000;
 
+    fn delay_ticks(&mut self, ticks: u64) {
         let mtime = MTIME;
         let t = mtime.mtime() + ticks;
         while mtime.mtime() < t {}
     }
+
+    /// Busy waits for at least the given [`fugit`] duration
+    ///
+    /// The duration is rounded up to whole mtime ticks (≈30.5 µs).
+    #[cfg(feature = "fugit")]
+    pub fn delay<const NOM: u32, const DENOM: u32>(
+        &mut self,
+        duration: fugit::Duration<u32, NOM, DENOM>,
+    ) {
+        // A fugit tick lasts NOM / DENOM seconds
+        let units = duration.ticks() as u64 * NOM as u64;
+        self.delay_ticks(units_to_ticks(units, DENOM as u64, TICKS_PER_SECOND));
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl Delay {
+    /// Waits for at least `ticks` whole mtime ticks, for the 1.0 `DelayNs` guarantees
+    pub(crate) fn delay_ticks_at_least(&mut self, ticks: u64) {
+        if ticks > 0 {
+            // The current tick is already partially elapsed
+            self.delay_ticks(ticks + 1);
+        }
+    }
+}
+
+/// Any non-zero delay lasts at least one whole mtime tick (≈30.5 µs)
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::delay::DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay_ticks_at_least(units_to_ticks(ns as u64, 1_000_000_000, TICKS_PER_SECOND));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay_ticks_at_least(units_to_ticks(us as u64, 1_000_000, TICKS_PER_SECOND));
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_ticks_at_least(units_to_ticks(ms as u64, 1_000, TICKS_PER_SECOND));
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_ticks(duration_to_ticks(Microseconds(us)));
+    }
 }
 
 // This is a workaround to allow `delay_us(42)` construction without specifying a type.
@@ -52,7 +99,8 @@ impl DelayUs<u8> for Delay {
 
 impl DelayMs<u32> for Delay {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1000);
+        // Converted in u64, `ms * 1000` overflows a u32 beyond 4_294_967 ms
+        self.delay_ticks(units_to_ticks(ms as u64, 1_000, TICKS_PER_SECOND));
     }
 }
 
@@ -79,7 +127,124 @@ impl DelayMs<u8> for Delay {
     }
 }
 
+/// Core clock busy loop delay provider
+///
+/// The actual duration of a loop iteration depends on the core pipeline, so the
+/// nominal cycle count derived from [Clocks] is only an estimate. Call
+/// [CycleDelay::calibrate] once at startup to measure it against mtime.
+pub struct CycleDelay {
+    cycles_per_ms: u32,
+}
+
+/// Duration of the calibration loop in milliseconds
+const CALIBRATION_MS: u32 = 10;
+
+impl CycleDelay {
+    /// Constructs a delay provider based on the nominal core clock frequency
+    pub fn new(clocks: Clocks) -> Self {
+        CycleDelay {
+            cycles_per_ms: (clocks.coreclk().0 / 1000).max(1),
+        }
+    }
+
+    /// Measures the delay loop against mtime and adjusts the number of cycles per
+    /// millisecond accordingly. This takes about 10 ms with interrupts disabled.
+    pub fn calibrate(&mut self) {
+        let cycles = self.cycles_per_ms * CALIBRATION_MS;
+        let mtime = MTIME;
+
+        let ticks = interrupt::free(|| {
+            // Start right after an mtime tick
+            let t = mtime.mtime();
+            while mtime.mtime() == t {}
+
+            let start = mtime.mtime();
+            unsafe { riscv::asm::delay(cycles) };
+            mtime.mtime() - start
+        });
+
+        if ticks > 0 {
+            let cycles_per_ms = cycles as u64 * TICKS_PER_SECOND / (ticks * 1000);
+            self.cycles_per_ms = (cycles_per_ms as u32).max(1);
+        }
+    }
+
+    /// Returns the number of delay loop cycles per millisecond currently in use
+    pub fn cycles_per_ms(&self) -> u32 {
+        self.cycles_per_ms
+    }
+
+    fn delay_cycles(&mut self, mut cycles: u64) {
+        while cycles > 0 {
+            let chunk = cycles.min(u32::MAX as u64);
+            unsafe { riscv::asm::delay(chunk as u32) };
+            cycles -= chunk;
+        }
+    }
+}
+
+impl DelayUs<u32> for CycleDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_cycles(us as u64 * self.cycles_per_ms as u64 / 1000);
+    }
+}
+
+// This is a workaround to allow `delay_us(42)` construction without specifying a type.
+impl DelayUs<i32> for CycleDelay {
+    #[inline(always)]
+    fn delay_us(&mut self, us: i32) {
+        assert!(us >= 0);
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayUs<u16> for CycleDelay {
+    #[inline(always)]
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32::from(us));
+    }
+}
+
+impl DelayUs<u8> for CycleDelay {
+    #[inline(always)]
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32::from(us));
+    }
+}
+
+impl DelayMs<u32> for CycleDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_cycles(ms as u64 * self.cycles_per_ms as u64);
+    }
+}
+
+// This is a workaround to allow `delay_ms(42)` construction without specifying a type.
+impl DelayMs<i32> for CycleDelay {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: i32) {
+        assert!(ms >= 0);
+        self.delay_ms(ms as u32);
+    }
+}
+
+impl DelayMs<u16> for CycleDelay {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32::from(ms));
+    }
+}
+
+impl DelayMs<u8> for CycleDelay {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32::from(ms));
+    }
+}
+
 /// Machine timer (mtime) as a sleep delay provider using mtimecmp
+///
+/// `Sleep` owns mtimecmp: it is left at `u64::MAX` after each sleep, so a timer
+/// interrupt enabled beforehand doesn't keep firing.
 pub struct Sleep {
     clock_freq: u32,
     mtimecmp: MTIMECMP,
@@ -93,19 +258,27 @@ impl Sleep {
             mtimecmp,
         }
     }
-}
 
-impl DelayMs<u32> for Sleep {
-    fn delay_ms(&mut self, ms: u32) {
-        let ticks = (ms as u64) * (self.clock_freq as u64) / 1000;
+    /// Sleeps for at least the given [`fugit`] duration
+    ///
+    /// The duration is rounded up to whole `lfclk` ticks.
+    #[cfg(feature = "fugit")]
+    pub fn delay<const NOM: u32, const DENOM: u32>(
+        &mut self,
+        duration: fugit::Duration<u32, NOM, DENOM>,
+    ) {
+        // A fugit tick lasts NOM / DENOM seconds
+        let units = duration.ticks() as u64 * NOM as u64;
+        self.sleep_ticks(units_to_ticks(units, DENOM as u64, self.clock_freq as u64));
+    }
+
+    fn sleep_ticks(&mut self, ticks: u64) {
         let t = MTIME.mtime() + ticks;
 
         self.mtimecmp.set_mtimecmp(t);
 
-        // Enable timer interrupt
-        unsafe {
-            mie::set_mtimer();
-        }
+        // Enable timer interrupt, its previous state is restored when the guard drops
+        let _timer = InterruptGuard::timer();
 
         // Wait For Interrupt will put CPU to sleep until an interrupt hits
         // in our case when internal timer mtime value >= mtimecmp value
@@ -122,13 +295,54 @@ impl DelayMs<u32> for Sleep {
             }
         }
 
-        // Clear timer interrupt
-        unsafe {
-            mie::clear_mtimer();
+        // Clear the pending timer interrupt before its enable is restored
+        self.mtimecmp.set_mtimecmp(u64::MAX);
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl Sleep {
+    /// Waits for at least `ticks` whole `lfclk` ticks, for the 1.0 `DelayNs` guarantees
+    fn wait_ticks_at_least(&mut self, ticks: u64) {
+        if ticks == 0 {
+            return;
+        }
+        // The current tick is already partially elapsed
+        let ticks = ticks + 1;
+        if ticks <= 2 {
+            // Too short to be worth sleeping, busy-wait instead
+            let t = MTIME.mtime() + ticks;
+            while MTIME.mtime() < t {}
+        } else {
+            self.sleep_ticks(ticks);
         }
     }
 }
 
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::delay::DelayNs for Sleep {
+    fn delay_ns(&mut self, ns: u32) {
+        let ticks = units_to_ticks(ns as u64, 1_000_000_000, self.clock_freq as u64);
+        self.wait_ticks_at_least(ticks);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        let ticks = units_to_ticks(us as u64, 1_000_000, self.clock_freq as u64);
+        self.wait_ticks_at_least(ticks);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        let ticks = units_to_ticks(ms as u64, 1_000, self.clock_freq as u64);
+        self.wait_ticks_at_least(ticks);
+    }
+}
+
+impl DelayMs<u32> for Sleep {
+    fn delay_ms(&mut self, ms: u32) {
+        self.sleep_ticks(units_to_ticks(ms as u64, 1_000, self.clock_freq as u64));
+    }
+}
+
 // This is a workaround to allow `delay_ms(42)` construction without specifying a type.
 impl DelayMs<i32> for Sleep {
     #[inline(always)]
@@ -151,3 +365,112 @@ impl DelayMs<u8> for Sleep {
         self.delay_ms(u32::from(ms));
     }
 }
+
+/// Machine timer (mtime) as a sleep delay provider that manages the timer
+/// interrupt enable through `critical-section`
+///
+/// Unlike [Sleep], machine interrupts are never touched directly: the timer
+/// interrupt enable is only changed inside critical sections, so the
+/// `critical-section` implementation of an RTOS stays in control. The hart waits
+/// for the timer inside a critical section, which WFI still wakes from, so no
+/// `MachineTimer` handler is needed. Other interrupts are serviced in between
+/// wake-ups.
+///
+/// `CsSleep` owns mtimecmp: it is left at `u64::MAX` after each sleep, so the
+/// timer interrupt doesn't stay pending.
+#[cfg(feature = "critical-section")]
+pub struct CsSleep {
+    clock_freq: u32,
+    mtimecmp: MTIMECMP,
+}
+
+#[cfg(feature = "critical-section")]
+impl CsSleep {
+    /// Constructs a delay provider using mtimecmp register to sleep
+    pub fn new(mtimecmp: MTIMECMP, clocks: Clocks) -> Self {
+        CsSleep {
+            clock_freq: clocks.lfclk().0,
+            mtimecmp,
+        }
+    }
+
+    /// Sleeps for at least the given [`fugit`] duration
+    ///
+    /// The duration is rounded up to whole `lfclk` ticks.
+    #[cfg(feature = "fugit")]
+    pub fn delay<const NOM: u32, const DENOM: u32>(
+        &mut self,
+        duration: fugit::Duration<u32, NOM, DENOM>,
+    ) {
+        // A fugit tick lasts NOM / DENOM seconds
+        let units = duration.ticks() as u64 * NOM as u64;
+        self.sleep_ticks(units_to_ticks(units, DENOM as u64, self.clock_freq as u64));
+    }
+
+    fn sleep_ticks(&mut self, ticks: u64) {
+        let t = MTIME.mtime() + ticks;
+
+        self.mtimecmp.set_mtimecmp(t);
+
+        let enabled = critical_section::with(|_| mie::read().mtimer());
+
+        loop {
+            let done = critical_section::with(|_| {
+                unsafe { mie::set_mtimer() };
+                if !mip::read().mtimer() {
+                    // Wakes on any pending enabled interrupt, even while masked
+                    unsafe { riscv::asm::wfi() };
+                }
+
+                let done = mip::read().mtimer();
+                if done {
+                    // Clears the pending timer interrupt
+                    self.mtimecmp.set_mtimecmp(u64::MAX);
+                }
+                // The timer interrupt must not be taken between wake-ups, as
+                // it would keep firing until mtimecmp is moved
+                if !done || !enabled {
+                    unsafe { mie::clear_mtimer() };
+                }
+                done
+            });
+
+            if done {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl DelayMs<u32> for CsSleep {
+    fn delay_ms(&mut self, ms: u32) {
+        self.sleep_ticks(units_to_ticks(ms as u64, 1_000, self.clock_freq as u64));
+    }
+}
+
+// This is a workaround to allow `delay_ms(42)` construction without specifying a type.
+#[cfg(feature = "critical-section")]
+impl DelayMs<i32> for CsSleep {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: i32) {
+        assert!(ms >= 0);
+        self.delay_ms(ms as u32);
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl DelayMs<u16> for CsSleep {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32::from(ms));
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl DelayMs<u8> for CsSleep {
+    #[inline(always)]
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32::from(ms));
+    }
+}
@@ -9,6 +9,93 @@ use e310x::{
 #[cfg(feature = "g002")]
 use e310x::{I2C0, UART1};
 
+/// FE310 chip variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// FE310-G000
+    G000,
+    /// FE310-G002, adds UART1 and I2C0
+    G002,
+}
+
+impl Variant {
+    /// Returns true if the variant has an I2C0 peripheral
+    pub const fn has_i2c(self) -> bool {
+        matches!(self, Variant::G002)
+    }
+
+    /// Returns true if the variant has a UART1 peripheral
+    pub const fn has_uart1(self) -> bool {
+        matches!(self, Variant::G002)
+    }
+
+    /// Returns the peripheral counts of the variant in a 48QFN package
+    pub const fn capabilities(self) -> Capabilities {
+        let g002 = matches!(self, Variant::G002);
+        Capabilities {
+            variant: self,
+            spi: 2,
+            uart: if g002 { 2 } else { 1 },
+            i2c: if g002 { 1 } else { 0 },
+            pwm: 3,
+            gpio_pins: 19,
+            dtim_bytes: crate::memory::DTIM.size,
+        }
+    }
+}
+
+/// Peripherals available in a chip package, as exposed by [DeviceResources]
+///
+/// The SPI count includes QSPI0, which normally drives the boot flash. QSPI2
+/// is not bonded out in the 48QFN package and is not counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Chip variant
+    pub variant: Variant,
+    /// Number of SPI controllers
+    pub spi: u8,
+    /// Number of UARTs
+    pub uart: u8,
+    /// Number of I2C controllers
+    pub i2c: u8,
+    /// Number of PWM controllers
+    pub pwm: u8,
+    /// Number of GPIO pins bonded out
+    pub gpio_pins: u8,
+    /// Size of the data memory (DTIM) in bytes
+    pub dtim_bytes: usize,
+}
+
+/// Returns the peripheral counts of [VARIANT], e.g. for a boot banner
+///
+/// ```
+/// let caps = e310x_hal::device::capabilities();
+/// assert_eq!(caps.pwm, 3);
+/// assert_eq!(caps.uart, if cfg!(feature = "g002") { 2 } else { 1 });
+/// ```
+pub const fn capabilities() -> Capabilities {
+    VARIANT.capabilities()
+}
+
+/// Chip variant this crate was built for, selected with the `g002` feature
+///
+/// The variants cannot be told apart at runtime. A build for the FE310-G000 also
+/// runs on FE310-G002 silicon, but without access to the G002-only peripherals.
+#[cfg(not(feature = "g002"))]
+pub const VARIANT: Variant = Variant::G000;
+/// Chip variant this crate was built for, selected with the `g002` feature
+///
+/// The variants cannot be told apart at runtime. A build for the FE310-G000 also
+/// runs on FE310-G002 silicon, but without access to the G002-only peripherals.
+#[cfg(feature = "g002")]
+pub const VARIANT: Variant = Variant::G002;
+
+/// True if the I2C0 peripheral is available in [VARIANT]
+pub const HAS_I2C: bool = VARIANT.has_i2c();
+
+/// True if the UART1 peripheral is available in [VARIANT]
+pub const HAS_UART1: bool = VARIANT.has_uart1();
+
 /// Device peripherals available in a 48QFN package, except GPIO0
 #[allow(non_snake_case)]
 pub struct DevicePeripherals {
@@ -172,8 +259,53 @@ impl DeviceResources {
         e310x::Peripherals::take().map(DeviceResources::from)
     }
 
+    /// Returns the chip variant this crate was built for (see [VARIANT])
+    #[inline]
+    pub const fn variant() -> Variant {
+        VARIANT
+    }
+
     /// Unchecked version of `DeviceResources::take`
     pub unsafe fn steal() -> Self {
         e310x::Peripherals::steal().into()
     }
 }
+
+/// Backup register written by [system_reset_with_flag]
+pub const RESET_FLAG_REGISTER: usize = 15;
+
+/// Resets the whole chip
+///
+/// The FE310 has no software reset register, so this arms the always-on watchdog
+/// with an immediate timeout. The reset is thus reported as a watchdog reset by
+/// the PMU cause register. The AON domain, including the backup registers, keeps
+/// its state.
+pub fn system_reset() -> ! {
+    unsafe { riscv::interrupt::disable() };
+
+    crate::wdog::write_unlocked(|wdog| wdog.wdogcmp.write(|w| unsafe { w.value().bits(0) }));
+    crate::wdog::write_unlocked(|wdog| {
+        wdog.wdogcfg
+            .write(|w| unsafe { w.scale().bits(0).rsten().set_bit().enalways().set_bit() })
+    });
+
+    loop {
+        unsafe { riscv::asm::wfi() };
+    }
+}
+
+/// Writes `flag` to the [RESET_FLAG_REGISTER] backup register and resets the chip
+///
+/// This lets a bootloader know why it was entered, e.g. to stay in update mode.
+/// Note that [PMUExt::store_backup](crate::pmu::PMUExt::store_backup) overwrites
+/// this register when storing 64 bytes.
+pub fn system_reset_with_flag(flag: u32) -> ! {
+    unsafe { (*BACKUP::ptr()).backup[RESET_FLAG_REGISTER].write(|w| w.bits(flag)) };
+
+    system_reset()
+}
+
+/// Reads the flag written by [system_reset_with_flag]
+pub fn reset_flag() -> u32 {
+    unsafe { (*BACKUP::ptr()).backup[RESET_FLAG_REGISTER].read().bits() }
+}
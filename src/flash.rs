@@ -0,0 +1,160 @@
+//! SPI flash (XIP) read timing and memory mapping
+//!
+//! Code and data in the [`FLASH`](crate::memory::FLASH) region are fetched by the
+//! QSPI0 controller in memory-mapped mode (`fctrl.en` set), using the read
+//! command described by the `ffmt` register. The read timing depends on:
+//!
+//! - the QSPI0 SCK frequency, `tlclk / (2 * (sckdiv + 1))`, see
+//!   [`Clocks::ramp_coreclk`](crate::clock::Clocks::ramp_coreclk)
+//! - `ffmt.pad_cnt`, the number of dummy cycles between the address and the data,
+//!   exposed as the [read latency](QspiFlash::read_latency)
+//!
+//! The FE310 has neither flash wait states nor a configurable sample delay: the
+//! data is always sampled on the SCK edge set by `sckmode`. High read latencies
+//! are only needed for fast-read commands, as required by the flash datasheet for
+//! the chosen SCK frequency.
+//!
+//! # Changing the timing while executing from flash
+//!
+//! Each register write applies to the next flash fetch, so the flash must be able
+//! to cope with both the old and the new setting in between. Lower the SCK
+//! frequency first (raise `sckdiv`), then change the read latency, then raise the
+//! SCK frequency again. Keep interrupts disabled during the whole sequence.
+//!
+//! # Programmed I/O access
+//!
+//! [QspiFlash] switches QSPI0 between memory-mapped mode and programmed I/O, e.g.
+//! to issue commands such as status reads or erases through
+//! [SpiBus](crate::spi::SpiBus) after [releasing](QspiFlash::release) the peripheral.
+//! The flash is not readable at its [`FLASH`](crate::memory::FLASH) addresses
+//! meanwhile, so that code must run from RAM.
+
+use e310x::{qspi0::fmt::PROTO_A, QSPI0};
+
+/// Read command used by memory-mapped flash accesses (`ffmt`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryMapConfig {
+    /// Command byte, e.g. `0x03` for a plain read or `0x6B` for a quad output read
+    pub cmd_code: u8,
+    /// Whether the command byte is sent at all, e.g. not in continuous read modes
+    pub cmd_enabled: bool,
+    /// Number of address bytes, 0 to 4
+    pub addr_len: u8,
+    /// Number of dummy cycles between the address and the data, 0 to 15
+    pub dummy_cycles: u8,
+    /// First 8 bits transmitted during the dummy cycles, e.g. mode bits
+    pub pad_code: u8,
+    /// Protocol of the command byte
+    pub cmd_protocol: PROTO_A,
+    /// Protocol of the address and dummy cycles
+    pub addr_protocol: PROTO_A,
+    /// Protocol of the data bytes
+    pub data_protocol: PROTO_A,
+}
+
+impl Default for MemoryMapConfig {
+    /// Plain single-line read (`0x03`) with 3 address bytes, the reset configuration
+    fn default() -> Self {
+        Self {
+            cmd_code: 0x03,
+            cmd_enabled: true,
+            addr_len: 3,
+            dummy_cycles: 0,
+            pad_code: 0,
+            cmd_protocol: PROTO_A::SINGLE,
+            addr_protocol: PROTO_A::SINGLE,
+            data_protocol: PROTO_A::SINGLE,
+        }
+    }
+}
+
+/// QSPI0 flash interface, switching between memory-mapped and programmed I/O mode
+pub struct QspiFlash {
+    qspi: QSPI0,
+}
+
+impl QspiFlash {
+    /// Wraps QSPI0, leaving its mode unchanged
+    pub fn new(qspi: QSPI0) -> Self {
+        Self { qspi }
+    }
+
+    /// Returns true if the flash is memory-mapped
+    pub fn is_memory_mapped(&self) -> bool {
+        self.qspi.fctrl.read().en().bit_is_set()
+    }
+
+    /// Programs the read command of `config` and enables memory-mapped mode
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.addr_len` is greater than 4 or `config.dummy_cycles` greater than 15.
+    ///
+    /// # Safety
+    ///
+    /// `config` must match the flash and its current mode, e.g. quad enable bit,
+    /// otherwise every subsequent fetch from flash returns garbage. Call this from
+    /// code running from RAM if the flash is already memory-mapped.
+    pub unsafe fn enable_memory_map(&mut self, config: &MemoryMapConfig) {
+        assert!(config.addr_len <= 4);
+        assert!(config.dummy_cycles <= 15);
+        self.qspi.ffmt.write(|w| {
+            w.cmd_en().bit(config.cmd_enabled);
+            w.addr_len().bits(config.addr_len);
+            w.pad_cnt().bits(config.dummy_cycles);
+            w.cmd_proto().bits(config.cmd_protocol as u8);
+            w.addr_proto().bits(config.addr_protocol as u8);
+            w.data_proto().bits(config.data_protocol as u8);
+            w.cmd_code().bits(config.cmd_code);
+            w.pad_code().bits(config.pad_code)
+        });
+        self.qspi.fctrl.write(|w| w.en().set_bit());
+    }
+
+    /// Disables memory-mapped mode, so that QSPI0 can be used for programmed I/O
+    ///
+    /// # Safety
+    ///
+    /// Nothing may be fetched from flash until memory-mapped mode is enabled again:
+    /// the caller must run from RAM with interrupts disabled, or with all the
+    /// interrupt handlers and their data in RAM.
+    pub unsafe fn disable_memory_map(&mut self) {
+        self.qspi.fctrl.write(|w| w.en().clear_bit());
+    }
+
+    /// Returns the SCK divisor, `sckdiv`
+    pub(crate) fn sck_div(&self) -> u16 {
+        self.qspi.sckdiv.read().div().bits()
+    }
+
+    /// Sets the SCK divisor, `sckdiv`, used by the next flash access
+    pub(crate) fn set_sck_div(&mut self, div: u16) {
+        self.qspi.sckdiv.write(|w| unsafe { w.div().bits(div) });
+    }
+
+    /// Returns the number of dummy cycles of memory-mapped flash reads
+    pub fn read_latency(&self) -> u8 {
+        self.qspi.ffmt.read().pad_cnt().bits()
+    }
+
+    /// Sets the number of dummy cycles of memory-mapped flash reads (`ffmt.pad_cnt`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cycles` is greater than 15.
+    ///
+    /// # Safety
+    ///
+    /// The new latency must match the read command in `ffmt.cmd_code` and the SCK
+    /// frequency, otherwise every subsequent instruction fetch from flash returns
+    /// garbage. See the [module documentation](self) for the procedure to follow.
+    pub unsafe fn set_read_latency(&mut self, cycles: u8) {
+        assert!(cycles <= 15);
+        self.qspi.ffmt.modify(|_, w| w.pad_cnt().bits(cycles));
+    }
+
+    /// Releases QSPI0, e.g. to build an [SpiBus](crate::spi::SpiBus) for programmed I/O
+    pub fn release(self) -> QSPI0 {
+        self.qspi
+    }
+}
@@ -1,4 +1,12 @@
 //! General Purpose I/O
+//!
+//! Inputs can be floating or pulled up, the FE310 has no internal pull-down resistors.
+//!
+//! The port has no separate set/clear registers, but every pin configuration and
+//! output change is a single atomic read-modify-write (`amoor`/`amoand`/`amoxor`),
+//! so pins of the same port can be driven from different contexts, including
+//! interrupt handlers, without a critical section. Targets without atomics fall
+//! back to `portable-atomic`, which guards the update with a critical section.
 
 use core::marker::PhantomData;
 
@@ -60,6 +68,22 @@ pub struct NoInvert;
 /// Invert output mode (type state)
 pub struct Invert;
 
+/// GPIO interrupt trigger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Low to high transition
+    RisingEdge,
+    /// High to low transition
+    FallingEdge,
+    /// High level
+    High,
+    /// Low level
+    Low,
+}
+
+/// GPIO interrupt event, as used by `listen` and friends
+pub type Event = Trigger;
+
 trait PinIndex {
     const INDEX: usize;
 }
@@ -73,7 +97,7 @@ fn atomic_set_bit(r: &AtomicU32, index: usize, bit: bool) {
     };
 }
 
-trait PeripheralAccess {
+pub(crate) trait PeripheralAccess {
     fn peripheral() -> &'static e310x::gpio0::RegisterBlock;
 
     fn input_value(index: usize) -> bool {
@@ -81,6 +105,11 @@ trait PeripheralAccess {
         (p.input_val.read().bits() >> (index & 31) & 1) != 0
     }
 
+    fn output_value(index: usize) -> bool {
+        let p = Self::peripheral();
+        (p.output_val.read().bits() >> (index & 31) & 1) != 0
+    }
+
     fn set_input_en(index: usize, bit: bool) {
         let p = Self::peripheral();
         let r: &AtomicU32 = unsafe { core::mem::transmute(&p.input_en) };
@@ -135,6 +164,62 @@ trait PeripheralAccess {
         let r: &AtomicU32 = unsafe { core::mem::transmute(&p.iof_sel) };
         atomic_set_bit(r, index, bit);
     }
+
+    fn interrupt_enabled(index: usize, trigger: Trigger) -> bool {
+        let p = Self::peripheral();
+        let bits = match trigger {
+            Trigger::RisingEdge => p.rise_ie.read().bits(),
+            Trigger::FallingEdge => p.fall_ie.read().bits(),
+            Trigger::High => p.high_ie.read().bits(),
+            Trigger::Low => p.low_ie.read().bits(),
+        };
+        (bits >> (index & 31) & 1) != 0
+    }
+
+    fn set_interrupt_enabled(index: usize, trigger: Trigger, bit: bool) {
+        let p = Self::peripheral();
+        match trigger {
+            Trigger::RisingEdge => {
+                let r: &AtomicU32 = unsafe { core::mem::transmute(&p.rise_ie) };
+                atomic_set_bit(r, index, bit);
+            }
+            Trigger::FallingEdge => {
+                let r: &AtomicU32 = unsafe { core::mem::transmute(&p.fall_ie) };
+                atomic_set_bit(r, index, bit);
+            }
+            Trigger::High => {
+                let r: &AtomicU32 = unsafe { core::mem::transmute(&p.high_ie) };
+                atomic_set_bit(r, index, bit);
+            }
+            Trigger::Low => {
+                let r: &AtomicU32 = unsafe { core::mem::transmute(&p.low_ie) };
+                atomic_set_bit(r, index, bit);
+            }
+        }
+    }
+
+    fn interrupt_pending(index: usize, trigger: Trigger) -> bool {
+        let p = Self::peripheral();
+        let bits = match trigger {
+            Trigger::RisingEdge => p.rise_ip.read().bits(),
+            Trigger::FallingEdge => p.fall_ip.read().bits(),
+            Trigger::High => p.high_ip.read().bits(),
+            Trigger::Low => p.low_ip.read().bits(),
+        };
+        (bits >> (index & 31) & 1) != 0
+    }
+
+    fn clear_interrupt_pending(index: usize, trigger: Trigger) {
+        let p = Self::peripheral();
+        let mask = 1 << (index & 31);
+        // Pending bits are cleared by writing 1, other bits are left untouched
+        match trigger {
+            Trigger::RisingEdge => p.rise_ip.write(|w| unsafe { w.bits(mask) }),
+            Trigger::FallingEdge => p.fall_ip.write(|w| unsafe { w.bits(mask) }),
+            Trigger::High => p.high_ip.write(|w| unsafe { w.bits(mask) }),
+            Trigger::Low => p.low_ip.write(|w| unsafe { w.bits(mask) }),
+        }
+    }
 }
 
 macro_rules! gpio {
@@ -144,13 +229,16 @@ macro_rules! gpio {
         /// GPIO
         pub mod $gpiox {
             use core::marker::PhantomData;
-            use core::convert::Infallible;
+            use core::convert::{Infallible, TryFrom};
 
             use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin,
                                ToggleableOutputPin};
             use e310x::$GPIOX;
             use super::{Unknown, IOF0, IOF1, Drive, Floating, GpioExt, Input, Invert,
-                        NoInvert, Output, PullUp, Regular, PinIndex, PeripheralAccess};
+                        NoInvert, Output, PullUp, Regular, PinIndex, PeripheralAccess, Trigger, Event};
+            use super::{AtomicU32, Ordering};
+            use crate::core::plic::{Plic, Priority};
+            use e310x::Interrupt;
 
             /// GPIO parts for fine grained permission control.
             pub struct Parts {
@@ -167,6 +255,153 @@ macro_rules! gpio {
                 }
             }
 
+            /// Reads the input values of all pins with a single register access
+            ///
+            /// Bit `n` holds the value of pin `n`. Only pins with the input enabled
+            /// report their state.
+            #[inline(always)]
+            pub fn read_input_all() -> u32 {
+                $GPIOX::peripheral().input_val.read().bits()
+            }
+
+            /// Writes the output values of all pins with a single register access
+            ///
+            /// Bit `n` sets the value of pin `n`.
+            ///
+            /// # Safety
+            ///
+            /// This overwrites the output state of every pin of the port, including
+            /// pins owned by other parts of the program. The caller must own all the
+            /// pins configured as outputs, or write back their current values.
+            #[inline(always)]
+            pub unsafe fn write_output_all(value: u32) {
+                $GPIOX::peripheral().output_val.write(|w| w.bits(value));
+            }
+
+            /// Toggles the output values of the pins in `mask` with a single
+            /// atomic read-modify-write
+            ///
+            /// Bit `n` set in `mask` flips pin `n`, so all the masked pins change
+            /// at the same time.
+            ///
+            /// # Safety
+            ///
+            /// The caller must own all the pins selected by `mask`.
+            #[inline(always)]
+            pub unsafe fn toggle_mask(mask: u32) {
+                let p = $GPIOX::peripheral();
+                let r: &AtomicU32 = core::mem::transmute(&p.output_val);
+                r.fetch_xor(mask, Ordering::SeqCst);
+            }
+
+            /// Pin with its index erased from the type, created with `erase()` or `into()`
+            ///
+            /// It converts back to the original pin type with `try_into()`, which
+            /// returns the erased pin as the error if the index does not match.
+            ///
+            /// All erased pins have the same type for a given mode, so they can be
+            /// stored in arrays and driven uniformly:
+            ///
+            /// ```no_run
+            /// use core::convert::TryInto;
+            /// use e310x_hal::{gpio::gpio0::{ErasedPin, Pin19}, prelude::*, DeviceResources};
+            ///
+            /// let pins = DeviceResources::take().unwrap().pins;
+            /// let mut leds = [
+            ///     pins.pin19.into_inverted_output().erase(),
+            ///     pins.pin21.into_inverted_output().erase(),
+            ///     pins.pin22.into_inverted_output().erase(),
+            /// ];
+            ///
+            /// let pattern = [true, false, true];
+            /// for (led, on) in leds.iter_mut().zip(pattern) {
+            ///     if on {
+            ///         led.set_high().unwrap();
+            ///     } else {
+            ///         led.set_low().unwrap();
+            ///     }
+            /// }
+            ///
+            /// let [first, ..] = leds;
+            /// let pin19: Pin19<_> = first.try_into().ok().unwrap();
+            /// ```
+            pub struct ErasedPin<MODE> {
+                index: usize,
+                _mode: PhantomData<MODE>,
+            }
+
+            impl<MODE> ErasedPin<MODE> {
+                /// Returns the index of the pin
+                pub fn index(&self) -> usize {
+                    self.index
+                }
+            }
+
+            impl<MODE> InputPin for ErasedPin<Input<MODE>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Infallible> {
+                    Ok($GPIOX::input_value(self.index))
+                }
+
+                fn is_low(&self) -> Result<bool, Infallible> {
+                    Ok(!self.is_high()?)
+                }
+            }
+
+            impl<MODE> ErasedPin<Output<MODE>> {
+                /// Enables or disables the high current drive of the pin
+                pub fn set_drive_strength(&mut self, high: bool) {
+                    $GPIOX::set_drive(self.index, high);
+                }
+
+                /// Enables or disables the input buffer of the pin, which
+                /// [read_pad](Self::read_pad) needs
+                pub fn set_pad_readback(&mut self, enable: bool) {
+                    $GPIOX::set_input_en(self.index, enable);
+                }
+
+                /// Returns the electrical level on the pad, see `read_pad` of the
+                /// typed pins
+                pub fn read_pad(&self) -> bool {
+                    $GPIOX::input_value(self.index)
+                }
+            }
+
+            impl<MODE> StatefulOutputPin for ErasedPin<Output<MODE>> {
+                fn is_set_high(&self) -> Result<bool, Infallible> {
+                    Ok($GPIOX::output_value(self.index))
+                }
+
+                fn is_set_low(&self) -> Result<bool, Infallible> {
+                    Ok(!self.is_set_high()?)
+                }
+            }
+
+            impl<MODE> OutputPin for ErasedPin<Output<MODE>> {
+                type Error = Infallible;
+
+                fn set_high(&mut self) -> Result<(), Infallible> {
+                    $GPIOX::set_output_value(self.index, true);
+                    Ok(())
+                }
+
+                fn set_low(&mut self) -> Result<(), Infallible> {
+                    $GPIOX::set_output_value(self.index, false);
+                    Ok(())
+                }
+            }
+
+            impl<MODE> ToggleableOutputPin for ErasedPin<Output<MODE>> {
+                type Error = Infallible;
+
+                /// Toggles the pin state.
+                fn toggle(&mut self) -> Result<(), Infallible> {
+                    $GPIOX::toggle_pin(self.index);
+                    Ok(())
+                }
+            }
+
             impl GpioExt for $GPIOX {
                 type Parts = Parts;
 
@@ -189,6 +424,24 @@ macro_rules! gpio {
                     const INDEX: usize = $i;
                 }
 
+                impl<MODE> From<$PXi<MODE>> for ErasedPin<MODE> {
+                    fn from(pin: $PXi<MODE>) -> Self {
+                        pin.erase()
+                    }
+                }
+
+                impl<MODE> TryFrom<ErasedPin<MODE>> for $PXi<MODE> {
+                    type Error = ErasedPin<MODE>;
+
+                    fn try_from(pin: ErasedPin<MODE>) -> Result<Self, Self::Error> {
+                        if pin.index == Self::INDEX {
+                            Ok($PXi { _mode: PhantomData })
+                        } else {
+                            Err(pin)
+                        }
+                    }
+                }
+
                 impl<MODE> $PXi<MODE> {
                     /// Configures the pin to serve as alternate function 0 (AF0)
                     pub fn into_iof0(self) -> $PXi<IOF0<NoInvert>> {
@@ -232,7 +485,7 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
-                    /// Configures the pin to operate as a pulled down input pin
+                    /// Configures the pin to operate as a pulled up input pin
                     pub fn into_pull_up_input(self) -> $PXi<Input<PullUp>> {
                         $GPIOX::set_pullup(Self::INDEX, true);
                         $GPIOX::set_input_en(Self::INDEX, true);
@@ -277,6 +530,102 @@ macro_rules! gpio {
                         $GPIOX::set_iof_en(Self::INDEX, false);
                         $PXi { _mode: PhantomData }
                     }
+
+                    /// Erases the pin index from the type
+                    pub fn erase(self) -> ErasedPin<MODE> {
+                        ErasedPin { index: Self::INDEX, _mode: PhantomData }
+                    }
+
+                    /// Returns true if the interrupt source for `trigger` is enabled
+                    pub fn interrupt_enabled(&self, trigger: Trigger) -> bool {
+                        $GPIOX::interrupt_enabled(Self::INDEX, trigger)
+                    }
+                }
+
+                impl<MODE> $PXi<Input<MODE>> {
+                    /// Enables or disables the internal pull-up resistor at runtime
+                    ///
+                    /// The type state is left as is, use [into_pull_up_input](Self::into_pull_up_input)
+                    /// or [into_floating_input](Self::into_floating_input) to track it in the type.
+                    pub fn set_pull_up(&mut self, enable: bool) {
+                        $GPIOX::set_pullup(Self::INDEX, enable);
+                    }
+
+                    /// Returns the PLIC interrupt source of the pin
+                    pub fn interrupt(&self) -> Interrupt {
+                        Interrupt::try_from(Interrupt::GPIO0 as u8 + Self::INDEX as u8).unwrap()
+                    }
+
+                    /// Enables the interrupt source for `trigger` and routes it to the
+                    /// PLIC with the given `priority`
+                    ///
+                    /// Any event latched before the call is discarded. Interrupts are
+                    /// only taken once the machine external interrupt is enabled too,
+                    /// e.g. with [Plic::external_interrupt_enabled].
+                    pub fn enable_interrupt_with_priority(
+                        &mut self,
+                        trigger: Trigger,
+                        priority: Priority,
+                        plic: &mut Plic,
+                    ) {
+                        let interrupt = self.interrupt();
+                        $GPIOX::clear_interrupt_pending(Self::INDEX, trigger);
+                        $GPIOX::set_interrupt_enabled(Self::INDEX, trigger, true);
+                        plic.set_priority(interrupt, priority);
+                        plic.enable_interrupt(interrupt);
+                    }
+
+                    /// Enables the interrupt source for `event`
+                    pub fn listen(&mut self, event: Event) {
+                        $GPIOX::set_interrupt_enabled(Self::INDEX, event, true);
+                    }
+
+                    /// Disables the interrupt source for `event`
+                    pub fn unlisten(&mut self, event: Event) {
+                        $GPIOX::set_interrupt_enabled(Self::INDEX, event, false);
+                    }
+
+                    /// Returns true if `event` is pending
+                    pub fn is_pending(&self, event: Event) -> bool {
+                        $GPIOX::interrupt_pending(Self::INDEX, event)
+                    }
+
+                    /// Clears the pending bit of `event`
+                    ///
+                    /// Level events stay pending for as long as the level is present.
+                    pub fn clear_pending(&mut self, event: Event) {
+                        $GPIOX::clear_interrupt_pending(Self::INDEX, event);
+                    }
+
+                    /// Blocks until a rising edge is detected on the pin.
+                    ///
+                    /// Any edge latched before the call is discarded. The rising
+                    /// edge interrupt enable is restored to its previous value on return.
+                    pub fn wait_for_rising_edge_blocking(&mut self) {
+                        let listening = self.interrupt_enabled(Trigger::RisingEdge);
+                        self.clear_pending(Trigger::RisingEdge);
+                        self.listen(Trigger::RisingEdge);
+                        while !self.is_pending(Trigger::RisingEdge) {}
+                        self.clear_pending(Trigger::RisingEdge);
+                        if !listening {
+                            self.unlisten(Trigger::RisingEdge);
+                        }
+                    }
+
+                    /// Blocks until a falling edge is detected on the pin.
+                    ///
+                    /// Any edge latched before the call is discarded. The falling
+                    /// edge interrupt enable is restored to its previous value on return.
+                    pub fn wait_for_falling_edge_blocking(&mut self) {
+                        let listening = self.interrupt_enabled(Trigger::FallingEdge);
+                        self.clear_pending(Trigger::FallingEdge);
+                        self.listen(Trigger::FallingEdge);
+                        while !self.is_pending(Trigger::FallingEdge) {}
+                        self.clear_pending(Trigger::FallingEdge);
+                        if !listening {
+                            self.unlisten(Trigger::FallingEdge);
+                        }
+                    }
                 }
 
                 impl<MODE> InputPin for $PXi<Input<MODE>> {
@@ -292,9 +641,38 @@ macro_rules! gpio {
                     }
                 }
 
+                impl<MODE> $PXi<Output<MODE>> {
+                    /// Enables or disables the high current drive of the pin at runtime
+                    ///
+                    /// Pins start in low drive after reset. Every pin of the port has
+                    /// a drive bit, and it affects the pad whether the pin is driven by
+                    /// software or by an IOF. The type state is left as is, use
+                    /// [into_output_drive](Self::into_output_drive) to track it in the type.
+                    pub fn set_drive_strength(&mut self, high: bool) {
+                        $GPIOX::set_drive(Self::INDEX, high);
+                    }
+
+                    /// Enables or disables the input buffer of the pin, which
+                    /// [read_pad](Self::read_pad) needs
+                    pub fn set_pad_readback(&mut self, enable: bool) {
+                        $GPIOX::set_input_en(Self::INDEX, enable);
+                    }
+
+                    /// Returns the electrical level on the pad, as opposed to the
+                    /// level the pin is set to drive
+                    ///
+                    /// This detects outputs held by something else, e.g. a shorted
+                    /// line. The input buffer must be enabled with
+                    /// [set_pad_readback](Self::set_pad_readback), otherwise this
+                    /// always returns `false`.
+                    pub fn read_pad(&self) -> bool {
+                        $GPIOX::input_value(Self::INDEX)
+                    }
+                }
+
                 impl<MODE> StatefulOutputPin for $PXi<Output<MODE>> {
                     fn is_set_high(&self) -> Result<bool, Infallible> {
-                        Ok($GPIOX::input_value(Self::INDEX))
+                        Ok($GPIOX::output_value(Self::INDEX))
                     }
 
                     fn is_set_low(&self) -> Result<bool, Infallible> {
@@ -9,13 +9,19 @@
 //! - SDA: Pin 12 IOF0
 //! - SCL: Pin 13 IOF0
 //! - Interrupt::I2C0
+//!
+//! # Notes
+//!
+//! The FE310 has no DMA controller, so all transfers are performed byte by byte
+//! by the CPU, polling the controller status after each byte.
 
 use crate::clock::Clocks;
-use crate::gpio::{gpio0, IOF0};
+use crate::core::clint::MTIME;
+use crate::gpio::{gpio0, PeripheralAccess, IOF0};
 use crate::time::Bps;
 use core::mem;
 use core::ops::Deref;
-use e310x::{i2c0, I2C0};
+use e310x::{i2c0, GPIO0, I2C0};
 use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
 
 /// SDA pin - DO NOT IMPLEMENT THIS TRAIT
@@ -39,7 +45,38 @@ pub enum Error {
     NoAck,
 }
 
+/// I2C device address
+///
+/// `u8` converts to a 7-bit address and `u16` to a 10-bit address, matching the
+/// `SevenBitAddress` and `TenBitAddress` modes of `embedded-hal`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Address {
+    /// 7-bit address
+    SevenBit(u8),
+
+    /// 10-bit address
+    TenBit(u16),
+}
+
+impl From<u8> for Address {
+    fn from(address: u8) -> Self {
+        Address::SevenBit(address)
+    }
+}
+
+impl From<u16> for Address {
+    fn from(address: u16) -> Self {
+        Address::TenBit(address)
+    }
+}
+
 /// Transmission speed
+///
+/// The controller divides `tlclk` by `5 * (prescaler + 1)` to generate SCL, and
+/// the prescaler is rounded up so the bus never runs faster than requested.
+/// `Fast` therefore needs `tlclk` of at least 2 MHz and is only hit exactly
+/// when `tlclk` is a multiple of 2 MHz; e.g. a 16 MHz `tlclk` gives 400 kHz,
+/// while 13.8 MHz gives about 394 kHz.
 pub enum Speed {
     /// 100Kbps
     Normal,
@@ -64,6 +101,97 @@ impl<SDA, SCL> I2c<I2C0, (SDA, SCL)> {
         SDA: SdaPin<I2C0>,
         SCL: SclPin<I2C0>,
     {
+        let mut i2c = Self {
+            i2c,
+            pins: (sda, scl),
+        };
+        i2c.set_speed(speed, clocks);
+        i2c
+    }
+
+    /// Frees a bus held by a device stuck in the middle of a transfer
+    ///
+    /// SCL and SDA are temporarily taken over as GPIO: up to 9 clock pulses are
+    /// generated until the device releases SDA, followed by a STOP condition.
+    /// The pins are then handed back to the I2C controller. SCL runs at about
+    /// 10 kHz during recovery, as it is timed with `mtime`.
+    ///
+    /// Returns [Error::InvalidState] if SDA is still held low afterwards.
+    pub fn recover(&mut self) -> Result<(), Error> {
+        const SDA: usize = 12;
+        const SCL: usize = 13;
+        let mask = (1 << SDA) | (1 << SCL);
+
+        let gpio = GPIO0::peripheral();
+        let input_en = gpio.input_en.read().bits() & mask;
+        let output_en = gpio.output_en.read().bits() & mask;
+        let out_xor = gpio.out_xor.read().bits() & mask;
+
+        // Emulate open drain outputs: a line is released by disabling its
+        // driver and pulled low by enabling it with a low output value
+        for pin in [SDA, SCL] {
+            GPIO0::set_output_en(pin, false);
+            GPIO0::set_out_xor(pin, false);
+            GPIO0::set_output_value(pin, false);
+            GPIO0::set_input_en(pin, true);
+            GPIO0::set_iof_en(pin, false);
+        }
+        half_period();
+
+        for _ in 0..9 {
+            if GPIO0::input_value(SDA) {
+                break;
+            }
+            GPIO0::set_output_en(SCL, true);
+            half_period();
+            GPIO0::set_output_en(SCL, false);
+            half_period();
+        }
+
+        // STOP condition: SDA rises while SCL is high
+        GPIO0::set_output_en(SCL, true);
+        GPIO0::set_output_en(SDA, true);
+        half_period();
+        GPIO0::set_output_en(SCL, false);
+        half_period();
+        GPIO0::set_output_en(SDA, false);
+        half_period();
+        let released = GPIO0::input_value(SDA);
+
+        for pin in [SDA, SCL] {
+            GPIO0::set_input_en(pin, input_en & (1 << pin) != 0);
+            GPIO0::set_output_en(pin, output_en & (1 << pin) != 0);
+            GPIO0::set_out_xor(pin, out_xor & (1 << pin) != 0);
+            GPIO0::set_iof_en(pin, true);
+        }
+
+        if released {
+            Ok(())
+        } else {
+            Err(Error::InvalidState)
+        }
+    }
+}
+
+/// Waits for at least one full `mtime` tick (about 30 us)
+fn half_period() {
+    let start = MTIME.mtime();
+    while MTIME.mtime() < start + 2 {}
+}
+
+impl<I2C, PINS> I2c<I2C, PINS> {
+    /// Releases the I2C peripheral and associated pins
+    pub fn free(self) -> (I2C, PINS) {
+        (self.i2c, self.pins)
+    }
+}
+
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> I2c<I2C, PINS> {
+    /// Changes the transmission speed
+    ///
+    /// The controller is briefly disabled, so this must not be called during a
+    /// transfer.
+    pub fn set_speed(&mut self, speed: Speed, clocks: Clocks) {
         // Calculate prescaler value
         let desired_speed = match speed {
             Speed::Normal => 100_000,
@@ -72,38 +200,27 @@ impl<SDA, SCL> I2c<I2C0, (SDA, SCL)> {
         };
         let clock = clocks.tlclk().0;
         assert!(desired_speed * 5 <= clock);
-        let prescaler = clock / (5 * desired_speed) - 1;
+        let divisor = 5 * desired_speed;
+        let prescaler = (clock + divisor - 1) / divisor - 1;
         assert!(prescaler < (1 << 16));
 
         // Turn off i2c
-        i2c.ctr.write(|w| w.en().clear_bit().ien().clear_bit());
+        self.i2c.ctr.write(|w| w.en().clear_bit().ien().clear_bit());
 
         // Set prescaler
         let prescaler_lo = (prescaler & 0xff) as u8;
         let prescaler_hi = ((prescaler >> 8) & 0xff) as u8;
-        i2c.prer_lo
+        self.i2c
+            .prer_lo
             .write(|w| unsafe { w.value().bits(prescaler_lo) });
-        i2c.prer_hi
+        self.i2c
+            .prer_hi
             .write(|w| unsafe { w.value().bits(prescaler_hi) });
 
         // Turn on i2c
-        i2c.ctr.write(|w| w.en().set_bit());
-
-        Self {
-            i2c,
-            pins: (sda, scl),
-        }
+        self.i2c.ctr.write(|w| w.en().set_bit());
     }
-}
 
-impl<I2C, PINS> I2c<I2C, PINS> {
-    /// Releases the I2C peripheral and associated pins
-    pub fn free(self) -> (I2C, PINS) {
-        (self.i2c, self.pins)
-    }
-}
-
-impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> I2c<I2C, PINS> {
     fn reset(&self) {
         // ACK pending interrupt event, clear commands
         self.write_cr(|w| w.iack().set_bit());
@@ -174,27 +291,55 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> I2c<I2C, PINS> {
     fn wait_for_complete(&self) {
         while self.read_sr().busy().bit_is_set() {}
     }
+
+    /// Generates a (repeated) start condition and writes `address` + R/W
+    ///
+    /// 10-bit addresses are sent as `0b11110xx` + W followed by the low address
+    /// byte. Reads then repeat the start condition with `0b11110xx` + R.
+    fn start(&self, address: Address, read: bool) -> Result<(), Error> {
+        let flag = if read { FLAG_READ } else { FLAG_WRITE };
+        match address {
+            Address::SevenBit(address) => {
+                self.write_byte((address << 1) + flag);
+                self.write_cr(|w| w.sta().set_bit().wr().set_bit());
+                self.wait_for_write()
+            }
+            Address::TenBit(address) => {
+                assert!(address < (1 << 10));
+                let header = 0b1111_0000 | ((address >> 7) as u8 & 0b110);
+                self.write_byte(header + FLAG_WRITE);
+                self.write_cr(|w| w.sta().set_bit().wr().set_bit());
+                self.wait_for_write()?;
+
+                self.write_byte(address as u8);
+                self.write_cr(|w| w.wr().set_bit());
+                self.wait_for_write()?;
+
+                if read {
+                    self.write_byte(header + FLAG_READ);
+                    self.write_cr(|w| w.sta().set_bit().wr().set_bit());
+                    self.wait_for_write()?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 const FLAG_READ: u8 = 1;
 const FLAG_WRITE: u8 = 0;
 
-impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Read for I2c<I2C, PINS> {
-    type Error = Error;
-
-    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> I2c<I2C, PINS> {
+    /// Reads enough bytes from the device at `address` to fill `buffer`
+    pub fn read_at(&mut self, address: Address, buffer: &mut [u8]) -> Result<(), Error> {
         self.reset();
 
         if self.read_sr().busy().bit_is_set() {
             return Err(Error::InvalidState);
         }
 
-        // Write address + R
-        self.write_byte((address << 1) + FLAG_READ);
-
-        // Generate start condition and write command
-        self.write_cr(|w| w.sta().set_bit().wr().set_bit());
-        self.wait_for_write()?;
+        // Generate start condition and write address + R
+        self.start(address, true)?;
 
         // Read bytes
         let buffer_len = buffer.len();
@@ -212,24 +357,17 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Read for I2c<I2C, PINS> {
         }
         Ok(())
     }
-}
-
-impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Write for I2c<I2C, PINS> {
-    type Error = Error;
 
-    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+    /// Writes `bytes` to the device at `address`
+    pub fn write_at(&mut self, address: Address, bytes: &[u8]) -> Result<(), Error> {
         self.reset();
 
         if self.read_sr().busy().bit_is_set() {
             return Err(Error::InvalidState);
         }
 
-        // Write address + W
-        self.write_byte((address << 1) + FLAG_WRITE);
-
-        // Generate start condition and write command
-        self.write_cr(|w| w.sta().set_bit().wr().set_bit());
-        self.wait_for_write()?;
+        // Generate start condition and write address + W
+        self.start(address, false)?;
 
         // Write bytes
         for (i, byte) in bytes.iter().enumerate() {
@@ -244,17 +382,54 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Write for I2c<I2C, PINS> {
         }
         Ok(())
     }
-}
 
-impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> WriteRead for I2c<I2C, PINS> {
-    type Error = Error;
+    /// Returns whether a device acknowledges `address`, using an empty write
+    pub fn probe(&mut self, address: u8) -> Result<bool, Error> {
+        self.reset();
 
-    fn write_read(
+        if self.read_sr().busy().bit_is_set() {
+            return Err(Error::InvalidState);
+        }
+
+        // Generate start condition and write address + W
+        match self.start(Address::SevenBit(address), false) {
+            Ok(()) => {
+                // Set STOP
+                self.write_cr(|w| w.sto().set_bit());
+                self.wait_for_complete();
+                Ok(true)
+            }
+            Err(Error::NoAck) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Probes the non-reserved addresses `0x08..=0x77` and stores the ones that
+    /// acknowledge in `found`
+    ///
+    /// Returns the number of responding devices, which may be larger than
+    /// `found.len()`; extra addresses are not stored.
+    pub fn scan(&mut self, found: &mut [u8]) -> Result<usize, Error> {
+        let mut count = 0;
+        for address in 0x08..=0x77 {
+            if self.probe(address)? {
+                if let Some(slot) = found.get_mut(count) {
+                    *slot = address;
+                }
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes `bytes` to the device at `address`, then reads enough bytes to
+    /// fill `buffer` after a repeated start condition
+    pub fn write_read_at(
         &mut self,
-        address: u8,
+        address: Address,
         bytes: &[u8],
         buffer: &mut [u8],
-    ) -> Result<(), Self::Error> {
+    ) -> Result<(), Error> {
         self.reset();
 
         if self.read_sr().busy().bit_is_set() {
@@ -262,18 +437,14 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> WriteRead for I2c<I2C, PINS
         }
 
         if !bytes.is_empty() && buffer.is_empty() {
-            self.write(address, bytes)
+            self.write_at(address, bytes)
         } else if !buffer.is_empty() && bytes.is_empty() {
-            self.read(address, buffer)
+            self.read_at(address, buffer)
         } else if bytes.is_empty() && buffer.is_empty() {
             Ok(())
         } else {
-            // Write address + W
-            self.write_byte((address << 1) + FLAG_WRITE);
-
-            // Generate start condition and write command
-            self.write_cr(|w| w.sta().set_bit().wr().set_bit());
-            self.wait_for_write()?;
+            // Generate start condition and write address + W
+            self.start(address, false)?;
 
             // Write bytes
             for byte in bytes {
@@ -283,12 +454,8 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> WriteRead for I2c<I2C, PINS
                 self.wait_for_write()?;
             }
 
-            // Write address + R
-            self.write_byte((address << 1) + FLAG_READ);
-
-            // Generate repeated start condition and write command
-            self.write_cr(|w| w.sta().set_bit().wr().set_bit());
-            self.wait_for_write()?;
+            // Generate repeated start condition and write address + R
+            self.start(address, true)?;
 
             // Read bytes
             let buffer_len = buffer.len();
@@ -309,3 +476,174 @@ impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> WriteRead for I2c<I2C, PINS
         }
     }
 }
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::i2c::Error for Error {
+    fn kind(&self) -> embedded_hal_1::i2c::ErrorKind {
+        use embedded_hal_1::i2c::{ErrorKind, NoAcknowledgeSource};
+
+        match self {
+            Error::InvalidState => ErrorKind::Bus,
+            Error::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            Error::NoAck => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<I2C, PINS> embedded_hal_1::i2c::ErrorType for I2c<I2C, PINS> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> I2c<I2C, PINS> {
+    /// Executes `operations` as a single I2C transaction with the device at `address`
+    ///
+    /// Consecutive operations of the same direction are merged, a repeated START
+    /// and the address are sent on every direction change, and only the final
+    /// operation ends with a STOP. The last byte read before a repeated START or
+    /// the STOP is not acknowledged.
+    ///
+    /// Zero-length operations are skipped. A transaction made only of zero-length
+    /// operations sends the address with the direction of the first operation,
+    /// followed by a STOP (e.g. to probe for a device). An empty slice does nothing.
+    pub fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [embedded_hal_1::i2c::Operation<'_>],
+    ) -> Result<(), Error> {
+        use embedded_hal_1::i2c::Operation;
+
+        fn is_read(op: &Operation<'_>) -> bool {
+            matches!(op, Operation::Read(_))
+        }
+
+        fn is_empty(op: &Operation<'_>) -> bool {
+            match op {
+                Operation::Read(buffer) => buffer.is_empty(),
+                Operation::Write(bytes) => bytes.is_empty(),
+            }
+        }
+
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        self.reset();
+
+        if self.read_sr().busy().bit_is_set() {
+            return Err(Error::InvalidState);
+        }
+
+        let mut next = operations.iter().position(|op| !is_empty(op));
+
+        if next.is_none() {
+            // Address only
+            self.start(address, is_read(&operations[0]))?;
+
+            self.write_cr(|w| w.sto().set_bit());
+            self.wait_for_complete();
+            return Ok(());
+        }
+
+        while let Some(start) = next {
+            let read = is_read(&operations[start]);
+
+            // Find the last operation of this direction before the next direction change
+            let mut end = start;
+            let mut index = start + 1;
+            while index < operations.len() {
+                let op = &operations[index];
+                if !is_empty(op) {
+                    if is_read(op) != read {
+                        break;
+                    }
+                    end = index;
+                }
+                index += 1;
+            }
+            next = if index < operations.len() {
+                Some(index)
+            } else {
+                None
+            };
+            let stop = next.is_none();
+
+            // Write address + R/W and generate a (repeated) start condition
+            self.start(address, read)?;
+
+            for (op_index, op) in operations[start..=end].iter_mut().enumerate() {
+                let last_op = start + op_index == end;
+                match op {
+                    Operation::Read(buffer) => {
+                        let buffer_len = buffer.len();
+                        for (i, byte) in buffer.iter_mut().enumerate() {
+                            let last = last_op && i == buffer_len - 1;
+                            // R + ACK, or R + NACK (+ STOP) for the last byte of the group
+                            self.write_cr(|w| {
+                                w.rd().set_bit().ack().bit(last).sto().bit(last && stop)
+                            });
+                            self.wait_for_read()?;
+
+                            *byte = self.read_byte();
+                        }
+                    }
+                    Operation::Write(bytes) => {
+                        let bytes_len = bytes.len();
+                        for (i, byte) in bytes.iter().enumerate() {
+                            let last = last_op && i == bytes_len - 1;
+                            self.write_byte(*byte);
+                            self.write_cr(|w| w.wr().set_bit().sto().bit(last && stop));
+                            self.wait_for_write()?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Read for I2c<I2C, PINS> {
+    type Error = Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_at(Address::SevenBit(address), buffer)
+    }
+}
+
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> Write for I2c<I2C, PINS> {
+    type Error = Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_at(Address::SevenBit(address), bytes)
+    }
+}
+
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> WriteRead for I2c<I2C, PINS> {
+    type Error = Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.write_read_at(Address::SevenBit(address), bytes, buffer)
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<I2C: Deref<Target = i2c0::RegisterBlock>, PINS> embedded_hal_1::i2c::I2c for I2c<I2C, PINS> {
+    /// Executes `operations` as a single I2C transaction
+    ///
+    /// See [I2c::transaction_at] for the exact bus sequence.
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_1::i2c::Operation<'_>],
+    ) -> Result<(), Error> {
+        self.transaction_at(Address::SevenBit(address), operations)
+    }
+}
@@ -12,7 +12,9 @@ pub mod clock;
 pub mod core;
 pub mod delay;
 pub mod device;
+pub mod flash;
 pub mod gpio;
+pub mod memory;
 pub mod pmu;
 pub mod prelude;
 pub mod pwm;
@@ -27,5 +29,9 @@ pub mod wdog;
 pub mod i2c;
 #[cfg(feature = "virq")]
 pub mod interrupt;
+#[cfg(feature = "log")]
+pub mod logger;
+#[cfg(feature = "async")]
+mod waker;
 
 pub use device::DeviceResources;
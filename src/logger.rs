@@ -0,0 +1,78 @@
+//! Logger
+//!
+//! A [`log`] backend writing records to a serial transmitter.
+//! You must activate the `log` feature to use this module.
+//!
+//! ```ignore
+//! let (tx, _rx) = serial.split();
+//! e310x_hal::logger::init(tx, log::LevelFilter::Info).unwrap();
+//!
+//! log::info!("Hello from the HiFive1");
+//! ```
+//!
+//! The maximum level can also be fixed at compile time with the `max_level_*`
+//! and `release_max_level_*` features of the `log` crate.
+
+use core::fmt::Write;
+use core::marker::PhantomData;
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use riscv::interrupt;
+
+use crate::serial::{Tx, UartX};
+use crate::stdout::Stdout;
+
+/// Logger writing formatted records to the transmitter of `UART`
+pub struct SerialLogger<UART> {
+    _uart: PhantomData<fn() -> UART>,
+}
+
+impl<UART> Log for SerialLogger<UART>
+where
+    UART: UartX + 'static,
+{
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        interrupt::free(|| {
+            // The transmitter was handed over in `init`
+            let mut tx = unsafe { Tx::<UART>::steal() };
+            let _ = writeln!(Stdout(&mut tx), "[{}] {}", record.level(), record.args());
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs a [SerialLogger] for the UART of `tx` as the global logger
+///
+/// The transmitter is consumed, as all further writes go through the logger.
+pub fn init<UART>(tx: Tx<UART>, level: LevelFilter) -> Result<(), SetLoggerError>
+where
+    UART: UartX + 'static,
+{
+    let logger: &'static SerialLogger<UART> = &SerialLogger { _uart: PhantomData };
+
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        log::set_logger(logger)?;
+        log::set_max_level(level);
+    }
+    // Targets without atomics only provide the racy setters, which are fine
+    // on a single hart as long as interrupts are masked
+    #[cfg(not(target_has_atomic = "ptr"))]
+    interrupt::free(|| unsafe {
+        log::set_logger_racy(logger)?;
+        log::set_max_level_racy(level);
+        Ok::<(), SetLoggerError>(())
+    })?;
+    core::mem::forget(tx);
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! FE310 memory map
+//!
+//! Addresses and sizes of the memory regions and peripheral register blocks
+//! of the FE310-G000 and FE310-G002, as listed in the FE310 manuals.
+
+/// A contiguous region of the address space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Start address of the region
+    pub base: usize,
+    /// Size of the region in bytes
+    pub size: usize,
+}
+
+impl Region {
+    /// Creates a region starting at `base` of `size` bytes
+    pub const fn new(base: usize, size: usize) -> Self {
+        Self { base, size }
+    }
+
+    /// Returns the first address past the end of the region
+    pub const fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    /// Returns true if `addr` lies within the region
+    ///
+    /// ```
+    /// use e310x_hal::memory::DTIM;
+    ///
+    /// assert!(DTIM.contains(0x8000_0000));
+    /// assert!(!DTIM.contains(0x8000_4000));
+    /// ```
+    pub const fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr < self.end()
+    }
+}
+
+/// Mask ROM, 8 KiB
+pub const MASK_ROM: Region = Region::new(0x0001_0000, 8 * 1024);
+/// OTP memory, read-only mapped, 8 KiB
+pub const OTP_MEMORY: Region = Region::new(0x0002_0000, 8 * 1024);
+/// Instruction tightly integrated memory, 8 KiB
+pub const ITIM: Region = Region::new(0x0800_0000, 8 * 1024);
+/// Memory-mapped (XIP) SPI flash behind QSPI0, 512 MiB window
+pub const FLASH: Region = Region::new(0x2000_0000, 512 * 1024 * 1024);
+/// Data tightly integrated memory (SRAM), 16 KiB
+pub const DTIM: Region = Region::new(0x8000_0000, 16 * 1024);
+
+/// Base address of the CLINT
+pub const CLINT_BASE: usize = 0x0200_0000;
+/// Base address of the PLIC
+pub const PLIC_BASE: usize = 0x0C00_0000;
+/// Base address of the always-on domain (WDOG, RTC, AONCLK, BACKUP, PMU)
+pub const AON_BASE: usize = 0x1000_0000;
+/// Base address of the PRCI
+pub const PRCI_BASE: usize = 0x1000_8000;
+/// Base address of the OTP controller
+pub const OTP_BASE: usize = 0x1001_0000;
+/// Base address of GPIO0
+pub const GPIO0_BASE: usize = 0x1001_2000;
+/// Base address of UART0
+pub const UART0_BASE: usize = 0x1001_3000;
+/// Base address of QSPI0
+pub const QSPI0_BASE: usize = 0x1001_4000;
+/// Base address of PWM0
+pub const PWM0_BASE: usize = 0x1001_5000;
+/// Base address of I2C0 (FE310-G002 only)
+#[cfg(feature = "g002")]
+pub const I2C0_BASE: usize = 0x1001_6000;
+/// Base address of UART1 (FE310-G002 only)
+#[cfg(feature = "g002")]
+pub const UART1_BASE: usize = 0x1002_3000;
+/// Base address of QSPI1
+pub const QSPI1_BASE: usize = 0x1002_4000;
+/// Base address of PWM1
+pub const PWM1_BASE: usize = 0x1002_5000;
+/// Base address of QSPI2
+pub const QSPI2_BASE: usize = 0x1003_4000;
+/// Base address of PWM2
+pub const PWM2_BASE: usize = 0x1003_5000;
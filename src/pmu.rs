@@ -1,4 +1,23 @@
 //! PMU Extension
+//!
+//! # Supply voltage monitoring
+//!
+//! The FE310 has no brown-out detector visible to software: the AON domain
+//! provides no low-voltage status bit, interrupt or wakeup source. A supply
+//! dip resets the chip through the power-on reset circuit and is reported as
+//! [`ResetCause::PowerOn`]. Firmware that needs to survive brown-outs should
+//! store its state in the backup registers ahead of time, or monitor the
+//! supply with an external comparator wired to a GPIO or the `dwakeup` pin.
+//!
+//! # Deep sleep
+//!
+//! [PMUExt::sleep] and [PMUExt::deep_sleep] run the PMU sleep program, which
+//! resets the core and powers off everything but the always-on (AON) domain.
+//! The core, the RAM, the PLL and every peripheral outside the AON domain lose
+//! their state: waking up runs the wake program and restarts the program from
+//! the reset vector, as after a reset, with [PMUExt::wakeup_cause] telling why.
+//! The RTC, the watchdog, the `lfclk` oscillators and the backup registers keep
+//! running, so store anything worth keeping with [PMUExt::store_backup] first.
 #![allow(missing_docs)]
 use e310x::{BACKUP, PMU, RTC};
 
@@ -74,6 +93,61 @@ pub enum WakeupCause {
     Digital,
 }
 
+/// PMU sleep or wake program, eight instructions run in order
+///
+/// Each instruction word holds the PMU output signals to drive (bits 4 and up,
+/// see the "Power Management Unit" chapter of the FE310 manual for their layout)
+/// and waits `2^delay` `lfclk` cycles after applying them (bits 3:0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmuSequence {
+    words: [u32; 8],
+}
+
+impl PmuSequence {
+    /// Builds a program from raw instruction words
+    pub const fn from_words(words: [u32; 8]) -> Self {
+        Self { words }
+    }
+
+    /// Default sleep program of the board, as loaded by [PMUExt::load_default_programs]
+    pub const fn default_sleep() -> Self {
+        Self::from_words(DEFAULT_SLEEP_PROGRAM)
+    }
+
+    /// Default wake program of the board, as loaded by [PMUExt::load_default_programs]
+    pub const fn default_wake() -> Self {
+        Self::from_words(DEFAULT_WAKE_PROGRAM)
+    }
+
+    /// Replaces the instruction at `index` with `signals` and a delay of `2^delay` cycles
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than 7, `delay` greater than 15, or `signals`
+    /// overlaps the delay field (bits 3:0).
+    pub fn instruction(mut self, index: usize, signals: u32, delay: u8) -> Self {
+        assert!(index < 8);
+        assert!(delay <= 15);
+        assert!(signals & 0xf == 0, "PMU signals overlap the delay field");
+        self.words[index] = signals | delay as u32;
+        self
+    }
+
+    /// Returns the raw instruction words
+    pub const fn words(&self) -> [u32; 8] {
+        self.words
+    }
+}
+
+/// Wakeup sources of [PMUExt::deep_sleep]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeupSources {
+    /// Wake up on the RTC alarm, see [Rtc::set_alarm](crate::rtc::Rtc::set_alarm)
+    pub rtc: bool,
+    /// Wake up when the `dwakeup` pin is pulled low, e.g. by a button
+    pub dwakeup: bool,
+}
+
 ///
 /// Errors for user data backup procedures
 ///
@@ -100,6 +174,19 @@ pub trait PMUExt {
     ///
     fn load_default_programs(&self);
 
+    ///
+    /// Loads `program` as the SLEEP program, unlocking each register write
+    ///
+    fn load_sleep_program(&self, program: &PmuSequence);
+
+    ///
+    /// Loads `program` as the WAKE program, unlocking each register write
+    ///
+    /// A wrong wake program can leave the device unable to wake up other than
+    /// through a power cycle.
+    ///
+    fn load_wake_program(&self, program: &PmuSequence);
+
     ///
     /// Puts device to sleep for N seconds, allowing wake-up button to wake it up as well
     ///
@@ -114,6 +201,18 @@ pub trait PMUExt {
     ///
     fn sleep(self, sleep_time: u32);
 
+    ///
+    /// Puts device to deep sleep until one of the `wakeup` sources triggers
+    ///
+    /// The RTC alarm, if selected, must be configured beforehand. See the
+    /// [module documentation](self) for the state lost during deep sleep.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no wakeup source is selected, as only a reset could wake the device.
+    ///
+    fn deep_sleep(self, wakeup: WakeupSources);
+
     ///
     /// Returns an enumified version of the Wakeup and Reset causes from the pmucause register
     ///
@@ -187,17 +286,55 @@ pub trait PMUExt {
     /// Clears all backup registers by setting each to zero
     ///
     fn clear_backup(&self);
+
+    ///
+    /// Returns the number of 32-bit backup registers
+    ///
+    fn backup_len(&self) -> usize;
+
+    ///
+    /// Reads the backup register `index`
+    ///
+    /// The backup registers are in the AON domain: they keep their value across
+    /// deep sleep and core resets, but are undefined after power loss. Store a
+    /// checksum or magic value next to the data to detect that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not below [backup_len](Self::backup_len).
+    ///
+    fn read_backup(&self, index: usize) -> u32;
+
+    ///
+    /// Writes `value` to the backup register `index`, see [read_backup](Self::read_backup)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not below [backup_len](Self::backup_len).
+    ///
+    fn write_backup(&self, index: usize, value: u32);
 }
 
 impl PMUExt for PMU {
     fn load_default_programs(&self) {
+        self.load_sleep_program(&PmuSequence::default_sleep());
+        self.load_wake_program(&PmuSequence::default_wake());
+    }
+
+    fn load_sleep_program(&self, program: &PmuSequence) {
         unsafe {
-            for i in 0..8 {
+            for (i, word) in program.words.iter().enumerate() {
                 self.pmukey.write(|w| w.bits(PMU_KEY_VAL));
-                self.pmusleeppm[i].write(|w| w.bits(DEFAULT_SLEEP_PROGRAM[i]));
+                self.pmusleeppm[i].write(|w| w.bits(*word));
+            }
+        }
+    }
 
+    fn load_wake_program(&self, program: &PmuSequence) {
+        unsafe {
+            for (i, word) in program.words.iter().enumerate() {
                 self.pmukey.write(|w| w.bits(PMU_KEY_VAL));
-                self.pmuwakepm[i].write(|w| w.bits(DEFAULT_WAKE_PROGRAM[i]));
+                self.pmuwakepm[i].write(|w| w.bits(*word));
             }
         }
     }
@@ -223,6 +360,18 @@ impl PMUExt for PMU {
         }
     }
 
+    fn deep_sleep(self, wakeup: WakeupSources) {
+        assert!(wakeup.rtc || wakeup.dwakeup, "no wakeup source");
+        unsafe {
+            // each pmu register needs key set before write
+            self.pmukey.write(|w| w.bits(PMU_KEY_VAL));
+            self.pmuie
+                .write(|w| w.rtc().bit(wakeup.rtc).dwakeup().bit(wakeup.dwakeup));
+            self.pmukey.write(|w| w.bits(PMU_KEY_VAL));
+            self.pmusleep.write(|w| w.sleep().set_bit());
+        }
+    }
+
     fn wakeup_cause(&self) -> Result<WakeupCause, CauseError> {
         let pmu_cause = self.pmucause.read();
         let wakeup_cause = pmu_cause.wakeupcause();
@@ -301,6 +450,26 @@ impl PMUExt for PMU {
         Ok(())
     }
 
+    fn backup_len(&self) -> usize {
+        unsafe { (*BACKUP::ptr()).backup.len() }
+    }
+
+    fn read_backup(&self, index: usize) -> u32 {
+        assert!(
+            index < self.backup_len(),
+            "backup register index out of range"
+        );
+        unsafe { (*BACKUP::ptr()).backup[index].read().bits() }
+    }
+
+    fn write_backup(&self, index: usize, value: u32) {
+        assert!(
+            index < self.backup_len(),
+            "backup register index out of range"
+        );
+        unsafe { (*BACKUP::ptr()).backup[index].write(|w| w.bits(value)) };
+    }
+
     fn clear_backup(&self) {
         unsafe {
             let backup = BACKUP::ptr();
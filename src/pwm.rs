@@ -151,8 +151,28 @@ pwmx_impl!(PWM2, u16);
 ///
 /// [PWM0] has a max period of 255, as it only has an 8 bit comparison register,
 /// the rest of them have a max value of 2^16 as they have 16 bit registers.
+///
+/// The duty of each channel is remembered, so that [enable](embedded_hal::Pwm::enable)
+/// after [disable](embedded_hal::Pwm::disable) resumes the last duty set:
+///
+/// ```no_run
+/// use e310x_hal::{pwm::{Channel, Pwm}, prelude::*, DeviceResources};
+///
+/// let dr = DeviceResources::take().unwrap();
+/// let channel = Channel::from(dr.pins.pin19.into_iof1());
+///
+/// let mut pwm = Pwm::new(dr.peripherals.PWM1);
+/// pwm.set_period(1000u16);
+/// pwm.set_duty(channel, 250);
+///
+/// pwm.disable(channel);
+/// assert_eq!(pwm.get_duty(channel), 0);
+/// pwm.enable(channel);
+/// assert_eq!(pwm.get_duty(channel), 250);
+/// ```
 pub struct Pwm<PWM> {
     pwm: PWM,
+    duty: [Option<u32>; 3],
 }
 
 impl<PWM: PwmX> Pwm<PWM> {
@@ -171,7 +191,18 @@ impl<PWM: PwmX> Pwm<PWM> {
         pwm.cmp1.reset();
         pwm.cmp2.reset();
         pwm.cmp3.reset();
-        Self { pwm }
+        Self {
+            pwm,
+            duty: [None; 3],
+        }
+    }
+
+    fn write_cmp(&mut self, index: CmpIndex, value: u32) {
+        match index {
+            CmpIndex::Cmp1 => self.pwm.cmp1.write(|w| unsafe { w.bits(value) }),
+            CmpIndex::Cmp2 => self.pwm.cmp2.write(|w| unsafe { w.bits(value) }),
+            CmpIndex::Cmp3 => self.pwm.cmp3.write(|w| unsafe { w.bits(value) }),
+        }
     }
 }
 
@@ -183,11 +214,9 @@ impl<PWM: PwmX> embedded_hal::Pwm for Pwm<PWM> {
     type Duty = PWM::CmpWidth;
 
     fn enable(&mut self, channel: Self::Channel) {
-        match channel.cmp_index {
-            CmpIndex::Cmp1 => self.pwm.cmp1.write(|w| unsafe { w.bits(u32::MAX) }),
-            CmpIndex::Cmp2 => self.pwm.cmp2.write(|w| unsafe { w.bits(u32::MAX) }),
-            CmpIndex::Cmp3 => self.pwm.cmp3.write(|w| unsafe { w.bits(u32::MAX) }),
-        }
+        // Resume the last duty set, if any
+        let duty = self.duty[channel.cmp_index as usize].unwrap_or(u32::MAX);
+        self.write_cmp(channel.cmp_index, duty);
     }
 
     fn disable(&mut self, channel: Self::Channel) {
@@ -217,11 +246,8 @@ impl<PWM: PwmX> embedded_hal::Pwm for Pwm<PWM> {
 
     fn set_duty(&mut self, channel: Self::Channel, duty: Self::Duty) {
         let duty = PWM::bits_from_cmp_width(duty.min(self.get_max_duty()));
-        match channel.cmp_index {
-            CmpIndex::Cmp1 => self.pwm.cmp1.write(|w| unsafe { w.bits(duty) }),
-            CmpIndex::Cmp2 => self.pwm.cmp2.write(|w| unsafe { w.bits(duty) }),
-            CmpIndex::Cmp3 => self.pwm.cmp3.write(|w| unsafe { w.bits(duty) }),
-        }
+        self.duty[channel.cmp_index as usize] = Some(duty);
+        self.write_cmp(channel.cmp_index, duty);
     }
 
     fn set_period<P>(&mut self, period: P)
@@ -233,3 +259,130 @@ impl<PWM: PwmX> embedded_hal::Pwm for Pwm<PWM> {
         self.pwm.cmp0.write(|w| unsafe { w.bits(period) });
     }
 }
+
+/// `cmp2gang` bit of the `cfg` register
+const CMP2GANG: u32 = 1 << 26;
+
+/// Complementary PWM pair with dead-time, e.g. to drive a half-bridge
+///
+/// The high-side channel is a regular PWM output. The low-side channel is ganged
+/// with the next comparator, so that it is active only in a window that keeps
+/// `dead_time` counts away from both edges of the high-side pulse. This means the
+/// comparator following the low-side one is used internally and its pin must not
+/// be used. The valid (high, low) channel combinations are thus (Cmp3, Cmp1) and
+/// (Cmp1, Cmp2).
+///
+/// # Notes
+///
+/// All channels share the counter of the PWM block, so the dead-time resolution is
+/// one counter tick, i.e. one `tlclk` cycle as [Pwm::new] leaves the prescaler at 1.
+pub struct ComplementaryPwm<PWM> {
+    pwm: Pwm<PWM>,
+    high: CmpIndex,
+    low: CmpIndex,
+    dead_time: u32,
+    duty: u32,
+}
+
+impl<PWM: PwmX> ComplementaryPwm<PWM> {
+    /// Configures `high` and `low` channels of `pwm` as a complementary pair
+    /// separated by `dead_time` counter ticks on each edge
+    ///
+    /// Both outputs stay inactive until a duty is set.
+    pub fn new(
+        pwm: Pwm<PWM>,
+        high: Channel<PWM>,
+        low: Channel<PWM>,
+        dead_time: PWM::CmpWidth,
+    ) -> Self {
+        let (high, low) = (high.cmp_index, low.cmp_index);
+        match (high, low) {
+            (CmpIndex::Cmp3, CmpIndex::Cmp1) => pwm.pwm.cfg.modify(|_, w| w.cmp1gang().set_bit()),
+            // The PAC describes `cmp2gang` as a multi-bit field, set the bit directly
+            (CmpIndex::Cmp1, CmpIndex::Cmp2) => pwm
+                .pwm
+                .cfg
+                .modify(|r, w| unsafe { w.bits(r.bits() | CMP2GANG) }),
+            _ => panic!("invalid complementary PWM channels"),
+        }
+
+        let mut this = Self {
+            pwm,
+            high,
+            low,
+            dead_time: PWM::bits_from_cmp_width(dead_time),
+            duty: 0,
+        };
+        this.set_duty(PWM::bits_into_cmp_width(0));
+        this
+    }
+
+    /// Sets the period in counter ticks and restarts the counter
+    ///
+    /// The duty is clamped to the new period.
+    pub fn set_period(&mut self, period: PWM::CmpWidth) {
+        self.pwm.pwm.count.reset();
+        self.pwm
+            .pwm
+            .cmp0
+            .write(|w| unsafe { w.bits(PWM::bits_from_cmp_width(period)) });
+        self.set_duty(PWM::bits_into_cmp_width(self.duty));
+    }
+
+    /// Returns the period in counter ticks
+    pub fn period(&self) -> PWM::CmpWidth {
+        PWM::bits_into_cmp_width(self.pwm.pwm.cmp0.read().bits())
+    }
+
+    /// Sets the number of ticks per period the high-side output is active
+    ///
+    /// The low-side output is active for the rest of the period minus twice the dead-time.
+    pub fn set_duty(&mut self, duty: PWM::CmpWidth) {
+        let period = self.pwm.pwm.cmp0.read().bits();
+        self.duty = PWM::bits_from_cmp_width(duty).min(period);
+
+        // The high side is active from its comparator until the end of the period
+        let high = if self.duty == 0 {
+            u32::MAX
+        } else {
+            period - self.duty
+        };
+        // The low side is active between its comparator and the next one
+        let low_start = self.dead_time.min(period);
+        let low_end = (period - self.duty)
+            .saturating_sub(self.dead_time)
+            .max(low_start);
+
+        self.write_cmp(self.high, high);
+        self.write_cmp(self.low, low_start);
+        let next = match self.low {
+            CmpIndex::Cmp1 => CmpIndex::Cmp2,
+            _ => CmpIndex::Cmp3,
+        };
+        self.write_cmp(next, low_end);
+    }
+
+    /// Returns the duty of the high-side output
+    pub fn duty(&self) -> PWM::CmpWidth {
+        PWM::bits_into_cmp_width(self.duty)
+    }
+
+    /// Returns the dead-time in counter ticks
+    pub fn dead_time(&self) -> PWM::CmpWidth {
+        PWM::bits_into_cmp_width(self.dead_time)
+    }
+
+    /// Ungangs the channels and releases the PWM device
+    pub fn release(self) -> Pwm<PWM> {
+        self.pwm.pwm.cfg.modify(|r, w| {
+            unsafe { w.bits(r.bits() & !CMP2GANG) }
+                .cmp1gang()
+                .clear_bit()
+        });
+        self.pwm
+    }
+
+    fn write_cmp(&mut self, index: CmpIndex, value: u32) {
+        self.pwm.write_cmp(index, value);
+    }
+}
@@ -3,6 +3,10 @@
 
 use e310x::RTC;
 
+#[cfg(feature = "fugit")]
+use crate::clock::Clocks;
+use crate::core::plic::Plic;
+
 pub trait RtcExt {
     fn constrain(self) -> Rtc;
 }
@@ -87,4 +91,68 @@ impl Rtc {
     pub fn set_rtccmp(&mut self, value: u32) {
         unsafe { (*RTC::ptr()).rtccmp.write(|w| w.bits(value)) };
     }
+
+    /// Returns the scaled counter, i.e. the counter bits compared against `rtccmp`
+    #[inline]
+    pub fn rtcs(&self) -> u32 {
+        unsafe { (*RTC::ptr()).rtcs.read().bits() }
+    }
+
+    /// Sets the alarm to go off once the scaled counter reaches `ticks`
+    ///
+    /// The alarm stays pending for as long as the scaled counter is at or above
+    /// `ticks`, so move it forward (or [clear_pending](Self::clear_pending)) in
+    /// the interrupt handler.
+    #[inline]
+    pub fn set_alarm(&mut self, ticks: u32) {
+        self.set_rtccmp(ticks);
+    }
+
+    /// Sets the alarm to go off `ticks` scaled counter ticks from now
+    pub fn set_alarm_in(&mut self, ticks: u32) {
+        self.set_rtccmp(self.rtcs().wrapping_add(ticks));
+    }
+
+    /// Clears the pending alarm by pushing the comparator to its maximum
+    pub fn clear_pending(&mut self) {
+        self.set_rtccmp(u32::MAX);
+    }
+
+    /// Routes the alarm to the machine external interrupt through the PLIC
+    ///
+    /// The RTC has no interrupt enable of its own, only the PLIC source is enabled.
+    pub fn listen(&mut self, plic: &mut Plic) {
+        plic.rtc.enable();
+    }
+
+    /// Returns the time elapsed since the counter was last set, at the `lfclk` rate
+    ///
+    /// The 48-bit counter is not affected by the scale, which only selects the
+    /// counter bits compared against the alarm. It rolls over to zero after
+    /// 2^48 `lfclk` cycles, almost 273 years at 32.768 kHz.
+    #[cfg(feature = "fugit")]
+    pub fn now(&self, clocks: &Clocks) -> fugit::MicrosDurationU64 {
+        let freq = clocks.lfclk().0 as u64;
+        let ticks = self.rtc();
+        // Split to keep the intermediate product within 64 bits
+        let micros = ticks / freq * 1_000_000 + ticks % freq * 1_000_000 / freq;
+        fugit::MicrosDurationU64::from_ticks(micros)
+    }
+
+    /// Sets the counter so that [now](Self::now) returns `time`, rounded down
+    /// to a whole `lfclk` cycle
+    ///
+    /// Times beyond the 48-bit range of the counter wrap around.
+    #[cfg(feature = "fugit")]
+    pub fn set_time(&mut self, time: fugit::MicrosDurationU64, clocks: &Clocks) {
+        let freq = clocks.lfclk().0 as u64;
+        let micros = time.ticks();
+        let ticks = micros / 1_000_000 * freq + micros % 1_000_000 * freq / 1_000_000;
+        self.set_rtc(ticks);
+    }
+
+    /// Stops routing the alarm to the PLIC
+    pub fn unlisten(&mut self, plic: &mut Plic) {
+        plic.rtc.disable();
+    }
 }
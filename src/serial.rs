@@ -16,12 +16,16 @@
 use core::convert::Infallible;
 use core::ops::Deref;
 
+use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::serial;
 use nb;
 
 use crate::clock::Clocks;
+use crate::core::clint::MTIME;
 use crate::gpio::{gpio0, IOF0};
-use crate::time::Bps;
+use crate::time::{Bps, RateError};
+#[cfg(feature = "async")]
+use crate::waker::WakerCell;
 use core::mem;
 #[allow(unused_imports)]
 use e310x::{uart0, UART0, UART1};
@@ -44,19 +48,95 @@ mod g002_ims {
 }
 
 #[doc(hidden)]
-pub trait UartX: Deref<Target = uart0::RegisterBlock> {}
-impl UartX for UART0 {}
-impl UartX for UART1 {}
+pub trait UartX: Deref<Target = uart0::RegisterBlock> {
+    /// GPIO index of the RX pin
+    const RX_PIN: usize;
+    #[cfg(feature = "async")]
+    fn registers() -> &'static uart0::RegisterBlock;
+    #[cfg(feature = "async")]
+    fn rx_waker() -> &'static WakerCell;
+    #[cfg(feature = "async")]
+    fn tx_waker() -> &'static WakerCell;
+}
+
+macro_rules! uartx {
+    ($($UART:ident: $rx_pin:expr,)+) => {
+        $(
+            impl UartX for $UART {
+                const RX_PIN: usize = $rx_pin;
+
+                #[cfg(feature = "async")]
+                fn registers() -> &'static uart0::RegisterBlock {
+                    unsafe { &*$UART::ptr() }
+                }
+
+                #[cfg(feature = "async")]
+                fn rx_waker() -> &'static WakerCell {
+                    static WAKER: WakerCell = WakerCell::new();
+                    &WAKER
+                }
+
+                #[cfg(feature = "async")]
+                fn tx_waker() -> &'static WakerCell {
+                    static WAKER: WakerCell = WakerCell::new();
+                    &WAKER
+                }
+            }
+        )+
+    };
+}
+
+uartx!(UART0: 16, UART1: 23,);
+
+/// Number of stop bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    /// 1 stop bit
+    One,
+    /// 2 stop bits
+    Two,
+}
 
 /// Serial abstraction
 pub struct Serial<UART, PINS> {
     uart: UART,
     pins: PINS,
+    baud_rate: Bps,
+}
+
+/// Baud rate divisor for `baud_rate`
+fn baud_divisor(baud_rate: Bps, clocks: Clocks) -> Result<u32, RateError> {
+    if baud_rate.0 == 0 {
+        return Err(RateError::TooSlow);
+    }
+    match clocks.tlclk().0 / baud_rate.0 {
+        cycles if cycles < 16 => Err(RateError::TooFast),
+        cycles if cycles > 0x1_0000 => Err(RateError::TooSlow),
+        cycles => Ok(cycles - 1),
+    }
+}
+
+/// Writes the divisor for `baud_rate` to `uart`, leaving it untouched if
+/// `baud_rate` is unachievable
+fn write_baud_rate<UART: UartX>(
+    uart: &UART,
+    baud_rate: Bps,
+    clocks: Clocks,
+) -> Result<(), RateError> {
+    let div = baud_divisor(baud_rate, clocks)?;
+    unsafe { uart.div.write(|w| w.bits(div)) };
+    Ok(())
 }
 
 /// Serial receiver
-pub struct Rx<UART> {
+///
+/// The receiver half of a [split](Serial::split) `Serial` also holds its pins and
+/// baud rate, so they can be given back by [Serial::reunite].
+pub struct Rx<UART, PINS = ()> {
     uart: UART,
+    break_detected: bool,
+    pins: PINS,
+    baud_rate: Bps,
 }
 
 /// Serial transmitter
@@ -66,12 +146,39 @@ pub struct Tx<UART> {
 
 impl<UART: UartX, TX, RX> Serial<UART, (TX, RX)> {
     /// Configures a UART peripheral to provide serial communication
+    ///
+    /// # Panics
+    ///
+    /// Panics if `baud_rate` can't be produced from `tlclk`, see [Serial::try_new].
     pub fn new(uart: UART, pins: (TX, RX), baud_rate: Bps, clocks: Clocks) -> Self
     where
         TX: TxPin<UART>,
         RX: RxPin<UART>,
     {
-        let div = clocks.tlclk().0 / baud_rate.0 - 1;
+        match Self::try_new(uart, pins, baud_rate, clocks) {
+            Ok(serial) => serial,
+            Err(_) => panic!("unachievable baud rate"),
+        }
+    }
+
+    /// Configures a UART peripheral to provide serial communication
+    ///
+    /// Returns the UART and pins back if `baud_rate` is above `tlclk / 16`, as the
+    /// receiver samples each bit 16 times, or below `tlclk / 65536`.
+    pub fn try_new(
+        uart: UART,
+        pins: (TX, RX),
+        baud_rate: Bps,
+        clocks: Clocks,
+    ) -> Result<Self, (RateError, UART, (TX, RX))>
+    where
+        TX: TxPin<UART>,
+        RX: RxPin<UART>,
+    {
+        let div = match baud_divisor(baud_rate, clocks) {
+            Ok(div) => div,
+            Err(err) => return Err((err, uart, pins)),
+        };
         unsafe {
             uart.ie.write(|w| w.txwm().bit(false).rxwm().bit(false));
             uart.div.write(|w| w.bits(div));
@@ -80,7 +187,42 @@ impl<UART: UartX, TX, RX> Serial<UART, (TX, RX)> {
             uart.rxctrl.write(|w| w.enable().bit(true));
         }
 
-        Serial { uart, pins }
+        Ok(Serial {
+            uart,
+            pins,
+            baud_rate,
+        })
+    }
+
+    /// Reapplies the baud rate after the core clock frequency has changed
+    ///
+    /// Wait for pending transmissions to complete before calling this. The
+    /// divisor is left untouched if the baud rate can't be produced from the new
+    /// `tlclk`.
+    pub fn reclock(&mut self, clocks: Clocks) -> Result<(), RateError> {
+        write_baud_rate(&self.uart, self.baud_rate, clocks)
+    }
+
+    /// Changes the baud rate
+    ///
+    /// Wait for pending transmissions to complete before calling this. If
+    /// `baud_rate` can't be produced from `tlclk`, the error is returned and the
+    /// current baud rate is kept.
+    pub fn reconfigure(&mut self, baud_rate: Bps, clocks: Clocks) -> Result<(), RateError> {
+        write_baud_rate(&self.uart, baud_rate, clocks)?;
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    /// Returns the baud rate divisor as currently configured in hardware
+    pub fn divisor(&self) -> u32 {
+        self.uart.div.read().bits()
+    }
+
+    /// Returns the baud rate the UART actually runs at, `tlclk / (div + 1)`,
+    /// which may differ from the requested one due to rounding
+    pub fn actual_baud_rate(&self, clocks: Clocks) -> Bps {
+        Bps(clocks.tlclk().0 / (self.divisor() + 1))
     }
 
     /// Starts listening for an interrupt event
@@ -97,24 +239,235 @@ impl<UART: UartX, TX, RX> Serial<UART, (TX, RX)> {
         self
     }
 
+    /// Sets the number of stop bits sent after each transmitted byte
+    ///
+    /// This only affects the transmitter: the receiver checks a single stop bit,
+    /// so it accepts frames with either one or two stop bits.
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        self.uart
+            .txctrl
+            .modify(|_, w| w.nstop().bit(stop_bits == StopBits::Two));
+    }
+
+    /// Returns the number of stop bits sent after each transmitted byte
+    pub fn stop_bits(&self) -> StopBits {
+        match self.uart.txctrl.read().nstop().bit() {
+            true => StopBits::Two,
+            false => StopBits::One,
+        }
+    }
+
+    /// Enables or disables the receiver, e.g. to ignore the own transmission on
+    /// a half-duplex link or to save power
+    pub fn enable_rx(&mut self, enable: bool) {
+        self.uart.rxctrl.modify(|_, w| w.enable().bit(enable));
+    }
+
+    /// Enables or disables the transmitter
+    pub fn enable_tx(&mut self, enable: bool) {
+        self.uart.txctrl.modify(|_, w| w.enable().bit(enable));
+    }
+
+    /// Blocks until all the written bytes have been completely transmitted,
+    /// including the last one in the shift register
+    pub fn flush_blocking(&mut self) {
+        wait_tx_idle(&self.uart);
+    }
+
     /// Splits the `Serial` abstraction into a transmitter and a
     /// receiver half
-    pub fn split(self) -> (Tx<UART>, Rx<UART>) {
+    pub fn split(self) -> (Tx<UART>, Rx<UART, (TX, RX)>) {
         (
-            Tx {
-                uart: unsafe { mem::zeroed() },
+            unsafe { Tx::steal() },
+            Rx {
+                uart: self.uart,
+                break_detected: false,
+                pins: self.pins,
+                baud_rate: self.baud_rate,
             },
-            Rx { uart: self.uart },
         )
     }
 
+    /// Recombines halves obtained from [split](Self::split), e.g. to [free](Self::free)
+    /// the pins afterwards
+    ///
+    /// The pins and the baud rate are the ones held by `rx`, see
+    /// [Tx::reconfigure] for baud rate changes made through the transmitter.
+    ///
+    /// ```no_run
+    /// use e310x_hal::{clock::Clocks, prelude::*, serial::Serial, DeviceResources};
+    ///
+    /// let dr = DeviceResources::take().unwrap();
+    /// let p = dr.peripherals;
+    /// let clocks = Clocks::freeze(p.PRCI.constrain(), p.AONCLK.constrain());
+    ///
+    /// let pins = (dr.pins.pin17.into_iof0(), dr.pins.pin16.into_iof0());
+    /// let (tx, rx) = Serial::new(p.UART0, pins, 115_200.bps(), clocks).split();
+    ///
+    /// let serial = Serial::reunite(tx, rx);
+    /// let (uart, (tx_pin, rx_pin)) = serial.free();
+    /// let _pin17 = tx_pin.into_output();
+    /// # drop((uart, rx_pin));
+    /// ```
+    pub fn reunite(tx: Tx<UART>, rx: Rx<UART, (TX, RX)>) -> Self {
+        drop(tx);
+        Serial {
+            uart: rx.uart,
+            pins: rx.pins,
+            baud_rate: rx.baud_rate,
+        }
+    }
+
     /// Releases the UART peripheral and associated pins
+    ///
+    /// The pins are returned with the same types that were passed to
+    /// [Serial::new], still in IOF0 mode, so they can be handed straight back
+    /// to it, e.g. to change the baud rate:
+    ///
+    /// ```no_run
+    /// use e310x_hal::{clock::Clocks, prelude::*, serial::Serial, DeviceResources};
+    ///
+    /// let dr = DeviceResources::take().unwrap();
+    /// let p = dr.peripherals;
+    /// let pins = dr.pins;
+    /// let clocks = Clocks::freeze(p.PRCI.constrain(), p.AONCLK.constrain());
+    ///
+    /// let tx = pins.pin17.into_iof0();
+    /// let rx = pins.pin16.into_iof0();
+    /// let serial = Serial::new(p.UART0, (tx, rx), 115_200.bps(), clocks);
+    ///
+    /// let (uart, pins) = serial.free();
+    /// let serial = Serial::new(uart, pins, 9_600.bps(), clocks);
+    /// # drop(serial);
+    /// ```
     pub fn free(self) -> (UART, (TX, RX)) {
         (self.uart, self.pins)
     }
 }
 
-impl<UART: UartX> serial::Read<u8> for Rx<UART> {
+impl<UART> Tx<UART> {
+    /// Creates a new handle to the transmitter of an already configured UART
+    pub(crate) unsafe fn steal() -> Self {
+        Tx {
+            uart: mem::zeroed(),
+        }
+    }
+}
+
+/// Blocks until the TX FIFO is empty and the last byte has been shifted out
+fn wait_tx_idle(uart: &uart0::RegisterBlock) {
+    // The FIFO count is below the watermark (1) once it is empty
+    while uart.ip.read().txwm().bit_is_clear() {}
+
+    wait_tx_frame(uart);
+}
+
+/// Blocks for the time it takes to shift out one frame
+fn wait_tx_frame(uart: &uart0::RegisterBlock) {
+    // There is no flag for the shift register, wait for a whole frame:
+    // start bit, 8 data bits and the stop bits (tlclk is the same as coreclk)
+    let bit_cycles = uart.div.read().bits() + 1;
+    let stop_bits = if uart.txctrl.read().nstop().bit_is_set() {
+        2
+    } else {
+        1
+    };
+    unsafe { riscv::asm::delay((9 + stop_bits) * bit_cycles) };
+}
+
+impl<UART: UartX> Tx<UART> {
+    /// Blocks until all the written bytes have been completely transmitted
+    ///
+    /// Unlike [`flush`](serial::Write::flush), which only waits for the TX FIFO
+    /// to drain, this also waits for the last byte to leave the shift register.
+    pub fn flush_blocking(&mut self) {
+        wait_tx_idle(&self.uart);
+    }
+
+    /// Enables or disables the transmitter
+    pub fn set_enabled(&mut self, enable: bool) {
+        self.uart.txctrl.modify(|_, w| w.enable().bit(enable));
+    }
+
+    /// Returns the baud rate divisor as currently configured in hardware
+    pub fn divisor(&self) -> u32 {
+        self.uart.div.read().bits()
+    }
+
+    /// Changes the baud rate, which is shared with the matching [Rx]
+    ///
+    /// The new rate isn't recorded in the [Rx], so [Serial::reunite] would keep
+    /// reapplying the previous one on [reclock](Serial::reclock). Prefer
+    /// [Rx::reconfigure] if the halves are to be reunited.
+    ///
+    /// If `baud_rate` can't be produced from `tlclk`, the error is returned and
+    /// the current baud rate is kept.
+    pub fn reconfigure(&mut self, baud_rate: Bps, clocks: Clocks) -> Result<(), RateError> {
+        write_baud_rate(&self.uart, baud_rate, clocks)
+    }
+}
+
+impl<UART: UartX, PINS> Rx<UART, PINS> {
+    /// Enables or disables the receiver
+    pub fn set_enabled(&mut self, enable: bool) {
+        self.uart.rxctrl.modify(|_, w| w.enable().bit(enable));
+    }
+
+    /// Returns the baud rate divisor as currently configured in hardware
+    pub fn divisor(&self) -> u32 {
+        self.uart.div.read().bits()
+    }
+
+    /// Changes the baud rate, which is shared with the matching [Tx]
+    ///
+    /// If `baud_rate` can't be produced from `tlclk`, the error is returned and
+    /// the current baud rate is kept.
+    pub fn reconfigure(&mut self, baud_rate: Bps, clocks: Clocks) -> Result<(), RateError> {
+        write_baud_rate(&self.uart, baud_rate, clocks)?;
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    /// Returns true if a BREAK condition was seen since the last
+    /// [clear_break](Self::clear_break)
+    ///
+    /// The UART doesn't report framing errors, so a BREAK is detected in software:
+    /// a BREAK is received as a 0x00 byte, and unlike a regular 0x00 byte the line
+    /// is still low once the byte is available. The line is sampled when
+    /// [read](serial::Read::read) returns a 0x00 byte and the RX FIFO is empty
+    /// afterwards, so a BREAK is missed if it ends before the byte is read. For
+    /// short BREAKs like the 13-bit LIN sync break, this leaves a few bit times, so
+    /// the receiver must be polled promptly or from the `rxwm` interrupt. The async
+    /// `Read` implementation doesn't detect BREAKs.
+    ///
+    /// The detection can't tell a BREAK from the start bit and zero data bits of
+    /// the next frame: reading a 0x00 byte while the next frame is arriving gives a
+    /// false positive. This happens with back-to-back 0x00 bytes, common in binary
+    /// protocols, so only rely on this where 0x00 bytes are rare or a BREAK is
+    /// expected, e.g. right before a LIN sync byte.
+    pub fn break_detected(&self) -> bool {
+        self.break_detected
+    }
+
+    /// Clears the latched BREAK condition
+    pub fn clear_break(&mut self) {
+        self.break_detected = false;
+    }
+
+    /// Discards all the bytes buffered in the RX FIFO and returns how many
+    ///
+    /// Note that there is no equivalent for the TX FIFO, as the UART cannot
+    /// abort a pending transmission.
+    pub fn clear_rx_fifo(&mut self) -> usize {
+        let mut count = 0;
+        while self.uart.rxdata.read().empty().bit_is_clear() {
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<UART: UartX, PINS> serial::Read<u8> for Rx<UART, PINS> {
     type Error = Infallible;
 
     fn read(&mut self) -> nb::Result<u8, Infallible> {
@@ -123,11 +476,24 @@ impl<UART: UartX> serial::Read<u8> for Rx<UART> {
         if rxdata.empty().bit_is_set() {
             Err(::nb::Error::WouldBlock)
         } else {
-            Ok(rxdata.data().bits() as u8)
+            let byte = rxdata.data().bits();
+            // Bytes still buffered were received after this one: the line then
+            // belongs to a later frame. The RX watermark is 0, so `rxwm` is pending
+            // as long as the FIFO isn't empty.
+            if byte == 0 && self.uart.ip.read().rxwm().bit_is_clear() && rx_line_low::<UART>() {
+                self.break_detected = true;
+            }
+            Ok(byte)
         }
     }
 }
 
+/// Returns true if the RX line of `UART` is currently low
+fn rx_line_low<UART: UartX>() -> bool {
+    // The pad input is sampled by the GPIO block even when the pin is driven by the IOF
+    crate::gpio::gpio0::read_input_all() & (1 << UART::RX_PIN) == 0
+}
+
 impl<UART: UartX> serial::Write<u8> for Tx<UART> {
     type Error = Infallible;
 
@@ -144,9 +510,14 @@ impl<UART: UartX> serial::Write<u8> for Tx<UART> {
         }
     }
 
+    /// Completes once the TX FIFO is empty
+    ///
+    /// The last byte may still be in the shift register at that point. Use
+    /// [flush_blocking](Tx::flush_blocking) before powering down the UART or
+    /// reconfiguring its pin, so the line does not get cut mid-frame.
     fn flush(&mut self) -> nb::Result<(), Infallible> {
         if self.uart.ip.read().txwm().bit_is_set() {
-            // FIFO count is below the receive watermark (1)
+            // FIFO count is below the transmit watermark (1), i.e. it is empty
             Ok(())
         } else {
             Err(nb::Error::WouldBlock)
@@ -154,6 +525,463 @@ impl<UART: UartX> serial::Write<u8> for Tx<UART> {
     }
 }
 
+/// Serial receiver with software idle-line detection
+///
+/// Keeps track of the `mtime` timestamp of the last received byte to detect the
+/// end of a frame once the line has been idle for a given number of bit times,
+/// as required by protocols such as Modbus RTU.
+///
+/// # Notes
+///
+/// - `mtime` is clocked by `lfclk` (≈32 kHz), so the gap is rounded up to whole
+///   `lfclk` ticks (≈30.5 µs). At high baud rates a tick spans several bit times.
+///
+/// - Timestamps are taken when bytes are read from the RX FIFO, not when they
+///   arrive. Bytes must be read promptly for the detection to be accurate.
+pub struct IdleRx<UART, PINS = ()> {
+    rx: Rx<UART, PINS>,
+    idle_ticks: u64,
+    last_rx: Option<u64>,
+}
+
+impl<UART: UartX, PINS> IdleRx<UART, PINS> {
+    /// Wraps a receiver that considers a frame complete after `idle_bits` bit
+    /// times without any received byte
+    pub fn new(rx: Rx<UART, PINS>, idle_bits: u32, baud_rate: Bps, clocks: Clocks) -> Self {
+        let ticks = (idle_bits as u64 * clocks.lfclk().0 as u64 + baud_rate.0 as u64 - 1)
+            / baud_rate.0 as u64;
+
+        IdleRx {
+            rx,
+            idle_ticks: ticks.max(1),
+            last_rx: None,
+        }
+    }
+
+    /// Returns true once the line has been idle long enough after receiving
+    /// at least one byte. The detection is re-armed by the next received byte.
+    pub fn frame_complete(&mut self) -> bool {
+        match self.last_rx {
+            // Bytes waiting in the RX FIFO belong to the current frame
+            Some(_) if self.rx.uart.ip.read().rxwm().bit_is_set() => false,
+            Some(last) if MTIME.mtime() - last >= self.idle_ticks => {
+                self.last_rx = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Releases the wrapped receiver
+    pub fn release(self) -> Rx<UART, PINS> {
+        self.rx
+    }
+}
+
+impl<UART: UartX, PINS> serial::Read<u8> for IdleRx<UART, PINS> {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        let byte = self.rx.read()?;
+        self.last_rx = Some(MTIME.mtime());
+        Ok(byte)
+    }
+}
+
+/// `embedded-io` implementations
+///
+/// Reads block until at least one byte is received, writes until at least one
+/// byte fits in the TX FIFO, and `flush` until the last byte has been shifted out.
+#[cfg(feature = "embedded-io")]
+mod io {
+    use core::convert::Infallible;
+
+    use e310x::uart0;
+    use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+
+    use super::{wait_tx_idle, Rx, Serial, Tx, UartX};
+
+    fn read(uart: &uart0::RegisterBlock, buf: &mut [u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        while count < buf.len() {
+            let rxdata = uart.rxdata.read();
+            if rxdata.empty().bit_is_set() {
+                if count > 0 {
+                    break;
+                }
+            } else {
+                buf[count] = rxdata.data().bits();
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn write(uart: &uart0::RegisterBlock, buf: &[u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        while count < buf.len() {
+            // Writes to a full FIFO are ignored, check before every write
+            if uart.txdata.read().full().bit_is_set() {
+                if count > 0 {
+                    break;
+                }
+            } else {
+                uart.txdata.write(|w| unsafe { w.data().bits(buf[count]) });
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn read_ready(uart: &uart0::RegisterBlock) -> Result<bool, Infallible> {
+        // Reading `rxdata` would pop a byte, rely on the RX watermark (0) instead
+        Ok(uart.ip.read().rxwm().bit_is_set())
+    }
+
+    fn write_ready(uart: &uart0::RegisterBlock) -> Result<bool, Infallible> {
+        Ok(uart.txdata.read().full().bit_is_clear())
+    }
+
+    fn flush(uart: &uart0::RegisterBlock) -> Result<(), Infallible> {
+        wait_tx_idle(uart);
+        Ok(())
+    }
+
+    impl<UART, PINS> ErrorType for Rx<UART, PINS> {
+        type Error = Infallible;
+    }
+
+    impl<UART: UartX, PINS> Read for Rx<UART, PINS> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            read(&self.uart, buf)
+        }
+    }
+
+    impl<UART: UartX, PINS> ReadReady for Rx<UART, PINS> {
+        fn read_ready(&mut self) -> Result<bool, Infallible> {
+            read_ready(&self.uart)
+        }
+    }
+
+    impl<UART> ErrorType for Tx<UART> {
+        type Error = Infallible;
+    }
+
+    impl<UART: UartX> Write for Tx<UART> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            write(&self.uart, buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            flush(&self.uart)
+        }
+    }
+
+    impl<UART: UartX> WriteReady for Tx<UART> {
+        fn write_ready(&mut self) -> Result<bool, Infallible> {
+            write_ready(&self.uart)
+        }
+    }
+
+    impl<UART, PINS> ErrorType for Serial<UART, PINS> {
+        type Error = Infallible;
+    }
+
+    impl<UART: UartX, PINS> Read for Serial<UART, PINS> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            read(&self.uart, buf)
+        }
+    }
+
+    impl<UART: UartX, PINS> ReadReady for Serial<UART, PINS> {
+        fn read_ready(&mut self) -> Result<bool, Infallible> {
+            read_ready(&self.uart)
+        }
+    }
+
+    impl<UART: UartX, PINS> Write for Serial<UART, PINS> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            write(&self.uart, buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            flush(&self.uart)
+        }
+    }
+
+    impl<UART: UartX, PINS> WriteReady for Serial<UART, PINS> {
+        fn write_ready(&mut self) -> Result<bool, Infallible> {
+            write_ready(&self.uart)
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use asynch::on_interrupt;
+
+/// `embedded-io-async` implementations
+///
+/// Reads wait for the RX watermark interrupt (at least one byte received) and
+/// writes for the TX watermark interrupt (TX FIFO drained). The UART interrupt must
+/// be enabled in the PLIC and its handler must call [on_interrupt]. Don't combine
+/// this with [Serial::listen], which enables the same interrupts.
+#[cfg(feature = "async")]
+mod asynch {
+    // The `async` feature requires Rust 1.75, see the README
+    #![allow(clippy::incompatible_msrv)]
+
+    use core::convert::Infallible;
+    use core::future::poll_fn;
+    use core::task::Poll;
+
+    use embedded_io_async::{Read, Write};
+    use riscv::interrupt;
+
+    use super::{wait_tx_frame, Rx, Serial, Tx, UartX};
+
+    /// Wakes the tasks waiting on the `UART` peripheral
+    ///
+    /// Call this from the interrupt handler of the UART peripheral when using the
+    /// async API. The pending watermark interrupts are masked until the tasks poll again.
+    pub fn on_interrupt<UART: UartX>() {
+        let uart = UART::registers();
+        let ip = uart.ip.read();
+        let (rx, tx) = interrupt::free(|| {
+            let ie = uart.ie.read();
+            let rx = ie.rxwm().bit_is_set() && ip.rxwm().bit_is_set();
+            let tx = ie.txwm().bit_is_set() && ip.txwm().bit_is_set();
+            uart.ie.modify(|_, w| {
+                w.rxwm()
+                    .bit(ie.rxwm().bit() && !rx)
+                    .txwm()
+                    .bit(ie.txwm().bit() && !tx)
+            });
+            (rx, tx)
+        });
+
+        if rx {
+            UART::rx_waker().wake();
+        }
+        if tx {
+            UART::tx_waker().wake();
+        }
+    }
+
+    /// Reads the buffered bytes into `buf`, waiting until at least one is available
+    async fn read<UART: UartX>(buf: &mut [u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let uart = UART::registers();
+        poll_fn(|cx| {
+            let mut count = 0;
+            while count < buf.len() {
+                let rxdata = uart.rxdata.read();
+                if rxdata.empty().bit_is_set() {
+                    break;
+                }
+                buf[count] = rxdata.data().bits();
+                count += 1;
+            }
+            if count > 0 {
+                return Poll::Ready(Ok(count));
+            }
+
+            UART::rx_waker().register(cx.waker());
+            interrupt::free(|| uart.ie.modify(|_, w| w.rxwm().set_bit()));
+            // A byte may have arrived before the interrupt was enabled
+            if uart.ip.read().rxwm().bit_is_set() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Writes as many bytes of `buf` as fit in the TX FIFO, waiting until at least one does
+    async fn write<UART: UartX>(buf: &[u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let uart = UART::registers();
+        poll_fn(|cx| {
+            let mut count = 0;
+            // Writes to a full FIFO are ignored, check before every write
+            while count < buf.len() && uart.txdata.read().full().bit_is_clear() {
+                uart.txdata.write(|w| unsafe { w.data().bits(buf[count]) });
+                count += 1;
+            }
+            if count > 0 {
+                return Poll::Ready(Ok(count));
+            }
+
+            wait_tx::<UART>(cx);
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Waits until the TX FIFO is empty and the last byte has been shifted out
+    ///
+    /// The FIFO is awaited, the final frame time is busy-waited as there is no
+    /// flag for the shift register.
+    async fn flush<UART: UartX>() -> Result<(), Infallible> {
+        let uart = UART::registers();
+        poll_fn(|cx| {
+            if uart.ip.read().txwm().bit_is_set() {
+                return Poll::Ready(());
+            }
+
+            wait_tx::<UART>(cx);
+            Poll::Pending
+        })
+        .await;
+
+        wait_tx_frame(uart);
+        Ok(())
+    }
+
+    fn wait_tx<UART: UartX>(cx: &mut core::task::Context<'_>) {
+        let uart = UART::registers();
+        UART::tx_waker().register(cx.waker());
+        interrupt::free(|| uart.ie.modify(|_, w| w.txwm().set_bit()));
+        // The FIFO may have drained before the interrupt was enabled
+        if uart.ip.read().txwm().bit_is_set() {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    impl<UART: UartX, PINS> Read for Rx<UART, PINS> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            read::<UART>(buf).await
+        }
+    }
+
+    impl<UART: UartX> Write for Tx<UART> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            write::<UART>(buf).await
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            flush::<UART>().await
+        }
+    }
+
+    impl<UART: UartX, PINS> Read for Serial<UART, PINS> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            read::<UART>(buf).await
+        }
+    }
+
+    impl<UART: UartX, PINS> Write for Serial<UART, PINS> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            write::<UART>(buf).await
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            flush::<UART>().await
+        }
+    }
+}
+
+/// Polarity of the RS-485 driver enable signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DePolarity {
+    /// The driver is enabled when the pin is high
+    ActiveHigh,
+    /// The driver is enabled when the pin is low
+    ActiveLow,
+}
+
+/// Half-duplex RS-485 serial link
+///
+/// Drives the transceiver driver enable (DE, usually tied to /RE) pin around
+/// every write: the receiver is disabled and DE asserted before transmitting, and
+/// DE is only released once the last stop bit has been shifted out, after which
+/// the receiver is enabled again.
+pub struct Rs485<UART, PINS, DE> {
+    serial: Serial<UART, PINS>,
+    de: DE,
+    polarity: DePolarity,
+}
+
+impl<UART: UartX, PINS, DE: OutputPin> Rs485<UART, PINS, DE> {
+    /// Wraps `serial` using `de` as the driver enable pin, released initially
+    pub fn new(
+        serial: Serial<UART, PINS>,
+        de: DE,
+        polarity: DePolarity,
+    ) -> Result<Self, DE::Error> {
+        let mut rs485 = Rs485 {
+            serial,
+            de,
+            polarity,
+        };
+        rs485.set_driver(false)?;
+        rs485.serial.uart.rxctrl.modify(|_, w| w.enable().bit(true));
+        Ok(rs485)
+    }
+
+    /// Transmits `bytes`, blocking until the last one has been completely sent
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<(), DE::Error> {
+        self.serial.uart.rxctrl.modify(|_, w| w.enable().bit(false));
+        if let Err(e) = self.set_driver(true) {
+            self.serial.uart.rxctrl.modify(|_, w| w.enable().bit(true));
+            return Err(e);
+        }
+
+        for &byte in bytes {
+            while self.serial.uart.txdata.read().full().bit_is_set() {}
+            self.serial
+                .uart
+                .txdata
+                .write(|w| unsafe { w.data().bits(byte) });
+        }
+        wait_tx_idle(&self.serial.uart);
+
+        let result = self.set_driver(false);
+        self.serial.uart.rxctrl.modify(|_, w| w.enable().bit(true));
+        result
+    }
+
+    /// Releases the serial interface and the driver enable pin
+    pub fn release(self) -> (Serial<UART, PINS>, DE) {
+        (self.serial, self.de)
+    }
+
+    fn set_driver(&mut self, enabled: bool) -> Result<(), DE::Error> {
+        if enabled == (self.polarity == DePolarity::ActiveHigh) {
+            self.de.set_high()
+        } else {
+            self.de.set_low()
+        }
+    }
+}
+
+impl<UART: UartX, PINS, DE> serial::Read<u8> for Rs485<UART, PINS, DE> {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        let rxdata = self.serial.uart.rxdata.read();
+
+        if rxdata.empty().bit_is_set() {
+            Err(::nb::Error::WouldBlock)
+        } else {
+            Ok(rxdata.data().bits())
+        }
+    }
+}
+
 // Backward compatibility
 impl<TX, RX> Serial<UART0, (TX, RX)> {
     /// Configures a UART peripheral to provide serial communication
@@ -48,17 +48,49 @@
 //! dev1.write(&[1, 2, 3]).unwrap();
 //! dev2.write(&[4, 5]).unwrap();
 //!```
+//!
+//! # Errors
+//! The controller keeps clocking frames as long as the TX FIFO holds data and
+//! drops received bytes when the 8-entry RX FIFO is full; it has no overrun or
+//! mode fault status. Transfers therefore never have more than 8 frames in
+//! flight, so a slow CPU only stalls the bus instead of losing data.
+//!
+//! The RX FIFO also keeps the bytes received for frames sent with
+//! `FullDuplex::send` until they are read. Once 8 of them are pending, sending,
+//! or starting a transfer with `flush_rx_on_start(false)`, fails with
+//! [Error::Overrun] instead of dropping data or waiting forever for bytes that
+//! never arrive.
+//!
+//! `transfer_with_timeout` reports [Error::Timeout] with the number of frames
+//! received when a stalled bus keeps it from completing in time.
+//!
+//! Drivers written for the former `Infallible` errors can use the deprecated
+//! [InfallibleSpi] wrapper for one more release.
+//!
+//! # Async usage
+//! With the `async` feature, [SpiBus] implements the `embedded-hal-async` `SpiBus`
+//! trait and the devices implement `SpiDevice`. Transfers are woken by the SPI
+//! interrupt, so it must be enabled in the PLIC and its handler must call
+//! `on_interrupt` for the corresponding peripheral.
 
+#[cfg(feature = "async")]
+mod async_traits; // embedded-hal-async implementations
 mod bus; // contains the SPI Bus abstraction
+mod compat; // deprecated Infallible errors
 mod config;
 mod exclusive_device; // contains the exclusive SPI device abstraction
+mod manual_cs; // manual CS control for the exclusive SPI device
 mod shared_bus; // shared bus newtype
 mod shared_device; // contains the shared SPI device abstraction
 mod traits; // contains SPI device abstraction
 
+#[cfg(feature = "async")]
+pub use async_traits::on_interrupt;
 pub use bus::*;
+pub use compat::*;
 pub use config::*;
 pub use exclusive_device::*;
+pub use manual_cs::*;
 pub use shared_bus::*;
 pub use shared_device::*;
 pub use traits::*;
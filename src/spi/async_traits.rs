@@ -0,0 +1,329 @@
+//! `embedded-hal-async` implementations
+//!
+//! Transfers yield while waiting for the RX FIFO and are woken by the RX watermark
+//! interrupt. The SPI interrupt must be enabled in the PLIC and its handler must
+//! call [on_interrupt].
+
+// The `async` feature requires Rust 1.75, see the README
+#![allow(clippy::incompatible_msrv)]
+
+use core::cell::RefMut;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_async::spi::{self, ErrorType, Operation};
+
+use crate::core::clint::MTIME;
+use crate::time::{units_to_ticks, MTIME_TICKS_PER_SECOND};
+
+use super::{Error, PinCS, Pins, SpiBus, SpiExclusiveDevice, SpiSharedDevice, SpiX};
+
+/// Wakes the task waiting on the `SPI` peripheral
+///
+/// Call this from the interrupt handler of the SPI peripheral when using the
+/// async API. The watermark interrupts are masked until the task polls again.
+pub fn on_interrupt<SPI: SpiX>() {
+    SPI::registers()
+        .ie
+        .modify(|_, w| w.rxwm().clear_bit().txwm().clear_bit());
+    SPI::waker().wake();
+}
+
+/// Restores the RX watermark of an async transfer once it completes or its future
+/// is dropped. The bytes still in flight are then discarded before the next access.
+struct TransferGuard<'a, SPI: SpiX, PINS> {
+    bus: &'a mut SpiBus<SPI, PINS>,
+    rxmark: u32,
+    in_flight: usize,
+}
+
+impl<SPI: SpiX, PINS> Drop for TransferGuard<'_, SPI, PINS> {
+    fn drop(&mut self) {
+        let spi = &self.bus.spi;
+        spi.ie.modify(|_, w| w.rxwm().clear_bit());
+        spi.rxmark.write(|w| unsafe { w.bits(self.rxmark) });
+        self.bus.unread += self.in_flight;
+    }
+}
+
+/// De-asserts CS once an exclusive device transaction completes or its future is
+/// dropped
+struct ExclusiveTransaction<'a, SPI: SpiX, PINS: Pins<SPI>>(&'a mut SpiExclusiveDevice<SPI, PINS>);
+
+impl<SPI: SpiX, PINS: Pins<SPI>> Drop for ExclusiveTransaction<'_, SPI, PINS> {
+    fn drop(&mut self) {
+        self.0.end_transaction();
+    }
+}
+
+/// Ends a shared device transaction once it completes or its future is dropped:
+/// de-asserts CS, releases the bus and wakes the tasks waiting for it
+struct SharedTransaction<'a, 'bus, SPI: SpiX, PINS, CS> {
+    device: &'a mut SpiSharedDevice<'bus, SPI, PINS, CS>,
+    bus: Option<RefMut<'bus, SpiBus<SPI, PINS>>>,
+}
+
+impl<SPI: SpiX, PINS, CS> Drop for SharedTransaction<'_, '_, SPI, PINS, CS> {
+    fn drop(&mut self) {
+        if let Some(mut bus) = self.bus.take() {
+            bus.end_frame();
+        }
+        SPI::bus_wakers().wake_all();
+        self.device.transaction_done();
+    }
+}
+
+impl<SPI, PINS> SpiBus<SPI, PINS>
+where
+    SPI: SpiX,
+{
+    /// Shifts out `write` (or `read` itself when `None`) while storing the received
+    /// bytes into `read`. The shorter buffer is padded with zeros or truncated, so
+    /// `len` bytes are always transferred.
+    async fn transfer_async(
+        &mut self,
+        read: &mut [u8],
+        write: Option<&[u8]>,
+        len: usize,
+    ) -> Result<(), Error> {
+        assert!(
+            !self.is_tx_only(),
+            "SPI async transfers need the RX direction"
+        );
+
+        let mut iwrite = 0;
+        let mut iread = 0;
+
+        // Ensure that RX FIFO is empty
+        self.flush_rx_fifo();
+        let room = self.rx_room()?;
+
+        // Raise the watermark interrupt as soon as a byte is received
+        let rxmark = self.spi.rxmark.read().bits();
+        self.spi.rxmark.write(|w| unsafe { w.rxmark().bits(0) });
+        let mut guard = TransferGuard {
+            bus: self,
+            rxmark,
+            in_flight: 0,
+        };
+        let bus = &mut *guard.bus;
+
+        while iread < len {
+            // Don't let more bytes in flight than the RX FIFO can hold
+            while iwrite < len
+                && iwrite - iread < room
+                && bus.spi.txdata.read().full().bit_is_clear()
+            {
+                let byte = match write {
+                    Some(write) => write.get(iwrite).copied().unwrap_or(0),
+                    None => read[iwrite],
+                };
+                iwrite += 1;
+                bus.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
+            }
+
+            let mut received = false;
+            loop {
+                let data = bus.spi.rxdata.read();
+                if data.empty().bit_is_set() {
+                    break;
+                }
+                if let Some(byte) = read.get_mut(iread) {
+                    *byte = data.data().bits();
+                }
+                iread += 1;
+                received = true;
+            }
+
+            if !received && iread < len {
+                guard.in_flight = iwrite - iread;
+                bus.wait_for_rx().await;
+            }
+        }
+
+        guard.in_flight = 0;
+        Ok(())
+    }
+
+    /// Waits until the RX watermark interrupt is pending
+    async fn wait_for_rx(&mut self) {
+        poll_fn(|cx| {
+            if self.spi.ip.read().rxwm().bit_is_set() {
+                return Poll::Ready(());
+            }
+
+            SPI::waker().register(cx.waker());
+            self.spi.ie.modify(|_, w| w.rxwm().set_bit());
+
+            // The byte may have arrived before the interrupt was enabled
+            if self.spi.ip.read().rxwm().bit_is_set() {
+                self.spi.ie.modify(|_, w| w.rxwm().clear_bit());
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    async fn exec_async(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        for op in operations {
+            match op {
+                Operation::Read(words) => {
+                    let len = words.len();
+                    self.transfer_async(words, Some(&[]), len).await?;
+                }
+                Operation::Write(words) => {
+                    self.transfer_async(&mut [], Some(words), words.len())
+                        .await?;
+                }
+                Operation::Transfer(read, write) => {
+                    let len = read.len().max(write.len());
+                    self.transfer_async(read, Some(write), len).await?;
+                }
+                Operation::TransferInPlace(words) => {
+                    let len = words.len();
+                    self.transfer_async(words, None, len).await?;
+                }
+                Operation::DelayNs(ns) => {
+                    let ticks = units_to_ticks(*ns as u64, 1_000_000_000, MTIME_TICKS_PER_SECOND);
+                    delay_ticks_at_least(ticks).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Waits for at least `ticks` whole mtime ticks
+///
+/// No timer interrupt is involved: the task yields and wakes itself right away,
+/// so the executor keeps polling it until the deadline, but other tasks still
+/// get to run in the meantime.
+async fn delay_ticks_at_least(ticks: u64) {
+    if ticks == 0 {
+        return;
+    }
+    // The current tick is already partially elapsed
+    let deadline = MTIME.mtime() + ticks + 1;
+    poll_fn(|cx| {
+        if MTIME.mtime() >= deadline {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+impl spi::Error for Error {
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            Error::Overrun => spi::ErrorKind::Overrun,
+            Error::Timeout { .. } => spi::ErrorKind::Other,
+        }
+    }
+}
+
+impl<SPI, PINS> ErrorType for SpiBus<SPI, PINS> {
+    type Error = Error;
+}
+
+impl<SPI, PINS> spi::SpiBus<u8> for SpiBus<SPI, PINS>
+where
+    SPI: SpiX,
+{
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let len = words.len();
+        self.transfer_async(words, Some(&[]), len).await
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        self.transfer_async(&mut [], Some(words), words.len()).await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        self.transfer_async(read, Some(write), len).await
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let len = words.len();
+        self.transfer_async(words, None, len).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        // Every transfer waits for all of its bytes to be received
+        Ok(())
+    }
+}
+
+impl<SPI, PINS> ErrorType for SpiExclusiveDevice<SPI, PINS> {
+    type Error = Error;
+}
+
+impl<SPI, PINS> spi::SpiDevice<u8> for SpiExclusiveDevice<SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.begin_transaction();
+        let transaction = ExclusiveTransaction(self);
+        let result = transaction.0.bus.exec_async(operations).await;
+        drop(transaction);
+
+        result
+    }
+}
+
+impl<SPI, PINS, CS> ErrorType for SpiSharedDevice<'_, SPI, PINS, CS> {
+    type Error = Error;
+}
+
+/// The bus stays borrowed for the whole transaction. Concurrent transactions of
+/// other async devices wait for it to be released, but blocking accesses to the
+/// bus (e.g. from interrupt handlers) in the meantime panic. Dropping the future
+/// of an ongoing transaction de-asserts CS and releases the bus.
+impl<SPI, PINS, CS> spi::SpiDevice<u8> for SpiSharedDevice<'_, SPI, PINS, CS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+    CS: PinCS<SPI>,
+{
+    // Holding the borrow is what locks the bus against other async devices
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.wait_ready();
+
+        let shared = self.bus;
+        let mut bus = poll_fn(|cx| {
+            if let Ok(bus) = shared.try_borrow_mut() {
+                return Poll::Ready(bus);
+            }
+
+            SPI::bus_wakers().register(cx.waker());
+            // The bus may have been released before the waker was registered
+            match shared.try_borrow_mut() {
+                Ok(bus) => Poll::Ready(bus),
+                Err(_) => Poll::Pending,
+            }
+        })
+        .await;
+
+        bus.configure(&self.config, Some(CS::CS_INDEX));
+        bus.start_frame();
+
+        let mut transaction = SharedTransaction {
+            device: self,
+            bus: Some(bus),
+        };
+        // The bus is only taken back when the transaction is dropped
+        let bus = transaction.bus.as_mut().unwrap();
+        let result = bus.exec_async(operations).await;
+        drop(transaction);
+
+        result
+    }
+}
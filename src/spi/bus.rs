@@ -1,16 +1,87 @@
-use core::convert::Infallible;
 use embedded_hal::blocking::spi::Operation;
 pub use embedded_hal::blocking::spi::{Transfer, Write, WriteIter};
 pub use embedded_hal::spi::{FullDuplex, Mode, Phase, Polarity, MODE_0, MODE_1, MODE_2, MODE_3};
 
+use e310x::qspi0::{csmode::MODE_A, fmt::PROTO_A};
 use nb;
 
-use super::{Pins, PinsNoCS, SharedBus, SpiConfig, SpiExclusiveDevice, SpiX};
+use crate::{clock::Clocks, core::clint::MTIME, time::Hertz};
+
+use super::{closest_divisor, Pins, PinsNoCS, SharedBus, SpiConfig, SpiExclusiveDevice, SpiX};
+
+/// Depth of the TX and RX FIFOs
+pub(crate) const FIFO_DEPTH: usize = 8;
+
+/// SPI error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The RX FIFO has no room for another received byte: `FIFO_DEPTH` (8)
+    /// frames were sent with `FullDuplex::send` and not read back, and the
+    /// controller drops received bytes instead of waiting for room
+    Overrun,
+    /// A transfer with a timeout gave up after receiving `received` frames
+    Timeout {
+        /// Number of frames received before the timeout
+        received: usize,
+    },
+}
 
 /// SPI bus abstraction
 pub struct SpiBus<SPI, PINS> {
     pub(crate) spi: SPI,
     pub(crate) pins: PINS,
+    stream: Option<Stream>,
+    flush_rx: bool,
+    /// Received bytes left in the RX FIFO by `FullDuplex::send`
+    pub(crate) unread: usize,
+    #[cfg(feature = "spi-trace")]
+    trace: Option<fn(u8, u8)>,
+}
+
+/// Snapshot of the SPI controller configuration registers
+#[derive(Clone, Copy, Debug)]
+pub struct BusState {
+    sckdiv: u32,
+    sckmode: u32,
+    csid: u32,
+    csdef: u32,
+    csmode: u32,
+    delay0: u32,
+    delay1: u32,
+    fmt: u32,
+    txmark: u32,
+    rxmark: u32,
+}
+
+/// State of an interrupt-driven streaming write
+struct Stream {
+    /// Remaining bytes of the buffer being transmitted
+    current: &'static [u8],
+    /// Buffer queued to be transmitted after `current`
+    next: Option<&'static [u8]>,
+    /// Bytes written to the TX FIFO whose RX counterpart is yet to be discarded
+    pending: usize,
+    /// Maximum number of bytes in flight
+    room: usize,
+    /// RX watermark to restore once the stream is done
+    rxmark: u8,
+}
+
+/// Inactive levels of the CS pins: high, except for `cs_index` if it is active high
+fn csdef(cs_index: u32, active_high: bool) -> u32 {
+    match active_high {
+        true => 0xffff & !(1 << cs_index),
+        false => 0xffff,
+    }
+}
+
+/// Number of data lines used by `protocol`
+fn data_lines(protocol: PROTO_A) -> u8 {
+    match protocol {
+        PROTO_A::SINGLE => 1,
+        PROTO_A::DUAL => 2,
+        PROTO_A::QUAD => 4,
+    }
 }
 
 impl<SPI, PINS> SpiBus<SPI, PINS>
@@ -22,73 +93,307 @@ where
     where
         PINS: Pins<SPI>,
     {
-        Self { spi, pins }
+        Self {
+            spi,
+            pins,
+            stream: None,
+            flush_rx: true,
+            unread: 0,
+            #[cfg(feature = "spi-trace")]
+            trace: None,
+        }
     }
 
     /// Releases the SPI peripheral and associated pins
-    pub fn release(self) -> (SPI, PINS) {
+    ///
+    /// An ongoing streaming write is aborted: its interrupts are disabled, the RX
+    /// watermark is restored and CS is de-asserted.
+    pub fn release(mut self) -> (SPI, PINS) {
+        if let Some(stream) = self.stream.take() {
+            self.spi.ie.reset();
+            self.spi
+                .rxmark
+                .write(|w| unsafe { w.rxmark().bits(stream.rxmark) });
+            self.end_frame();
+        }
         (self.spi, self.pins)
     }
 
+    /// Panics if a streaming write is in progress, as any other access would
+    /// mix its bytes or settings with the stream
+    pub(crate) fn assert_no_stream(&self) {
+        assert!(self.stream.is_none(), "SPI streaming write in progress");
+    }
+
+    /// Sets the transmit watermark: the `txwm` interrupt is pending while the
+    /// TX FIFO holds fewer than `value` entries
+    ///
+    /// Shared devices apply the watermarks of their [SpiConfig] at the start of
+    /// every transaction, overriding this setting.
+    pub fn set_tx_watermark(&mut self, value: u8) {
+        self.assert_no_stream();
+        self.spi.txmark.write(|w| unsafe { w.txmark().bits(value) });
+    }
+
+    /// Sets the receive watermark: the `rxwm` interrupt is pending while the
+    /// RX FIFO holds more than `value` entries
+    ///
+    /// Shared devices apply the watermarks of their [SpiConfig] at the start of
+    /// every transaction, overriding this setting.
+    pub fn set_rx_watermark(&mut self, value: u8) {
+        self.assert_no_stream();
+        self.spi.rxmark.write(|w| unsafe { w.rxmark().bits(value) });
+    }
+
+    /// Sets the SCK frequency to the highest one that does not exceed `target`
+    /// and returns it (see [closest_divisor])
+    pub fn set_closest_frequency(&mut self, target: Hertz, clocks: Clocks) -> Hertz {
+        let div = closest_divisor(clocks.tlclk(), target);
+        self.set_clock_divisor(div);
+        Hertz(clocks.tlclk().0 / (2 * (div + 1)))
+    }
+
+    /// Returns the SCK divisor currently configured in hardware
+    pub(crate) fn clock_divisor(&self) -> u32 {
+        self.spi.sckdiv.read().div().bits() as u32
+    }
+
+    /// Sets the SCK divisor, `tlclk / (2 * (div + 1))`
+    pub(crate) fn set_clock_divisor(&mut self, div: u32) {
+        self.assert_no_stream();
+        self.spi
+            .sckdiv
+            .write(|w| unsafe { w.div().bits(div as u16) });
+    }
+
+    /// Returns the current CS mode of the controller
+    ///
+    /// Transactions switch between `HOLD` while CS is asserted and `AUTO` otherwise,
+    /// unless the configured mode is `OFF`.
+    pub fn cs_mode(&self) -> MODE_A {
+        // All the 2-bit values are valid modes but 3, which is reserved and reads as OFF
+        self.spi
+            .csmode
+            .read()
+            .mode()
+            .variant()
+            .unwrap_or(MODE_A::OFF)
+    }
+
+    /// Captures the current controller configuration, e.g. before handing the
+    /// peripheral to code that reconfigures it behind the back of the HAL
+    pub fn save_state(&self) -> BusState {
+        BusState {
+            sckdiv: self.spi.sckdiv.read().bits(),
+            sckmode: self.spi.sckmode.read().bits(),
+            csid: self.spi.csid.read().bits(),
+            csdef: self.spi.csdef.read().bits(),
+            csmode: self.spi.csmode.read().bits(),
+            delay0: self.spi.delay0.read().bits(),
+            delay1: self.spi.delay1.read().bits(),
+            fmt: self.spi.fmt.read().bits(),
+            txmark: self.spi.txmark.read().bits(),
+            rxmark: self.spi.rxmark.read().bits(),
+        }
+    }
+
+    /// Restores a controller configuration captured with [save_state](Self::save_state)
+    pub fn restore_state(&mut self, state: &BusState) {
+        self.assert_no_stream();
+        unsafe {
+            self.spi.sckdiv.write(|w| w.bits(state.sckdiv));
+            self.spi.sckmode.write(|w| w.bits(state.sckmode));
+            self.spi.csid.write(|w| w.bits(state.csid));
+            self.spi.csdef.write(|w| w.bits(state.csdef));
+            self.spi.csmode.write(|w| w.bits(state.csmode));
+            self.spi.delay0.write(|w| w.bits(state.delay0));
+            self.spi.delay1.write(|w| w.bits(state.delay1));
+            self.spi.fmt.write(|w| w.bits(state.fmt));
+            self.spi.txmark.write(|w| w.bits(state.txmark));
+            self.spi.rxmark.write(|w| w.bits(state.rxmark));
+        }
+    }
+
+    /// Selects the hardware CS line `cs_index` and sets the inactive level of
+    /// the CS pins, high unless the selected one is active high
+    pub(crate) fn select_cs(&mut self, cs_index: u32, active_high: bool) {
+        self.assert_no_stream();
+        if self.spi.csid.read().bits() != cs_index {
+            self.spi.csid.write(|w| unsafe { w.bits(cs_index) });
+        }
+        let csdef = csdef(cs_index, active_high);
+        if self.spi.csdef.read().bits() != csdef {
+            self.spi.csdef.write(|w| unsafe { w.bits(csdef) });
+        }
+    }
+
     /// Configure the [SpiBus] with given [SpiConfig]
+    ///
+    /// Registers already holding the requested value are not written again,
+    /// which keeps back-to-back transactions of the same device cheap.
     pub(crate) fn configure(&mut self, config: &SpiConfig, cs_index: Option<u32>)
     where
         PINS: Pins<SPI>,
     {
-        self.spi
-            .sckdiv
-            .write(|w| unsafe { w.div().bits(config.clock_divisor as u16) });
+        self.flush_rx = config.flush_rx_on_start;
 
-        if let Some(index) = cs_index {
-            self.spi.csid.write(|w| unsafe { w.bits(index) });
+        let div = config.clock_divisor as u16;
+        if self.spi.sckdiv.read().div().bits() != div {
+            self.spi.sckdiv.write(|w| unsafe { w.div().bits(div) });
         }
-        self.spi.csmode.write(|w| w.mode().variant(config.cs_mode));
 
-        // Set CS pin polarity to high
-        self.spi.csdef.reset();
+        let index = cs_index.unwrap_or_else(|| self.spi.csid.read().bits());
+        self.select_cs(index, config.cs_active_high);
+        if self.spi.csmode.read().mode().variant() != Some(config.cs_mode) {
+            self.spi.csmode.write(|w| w.mode().variant(config.cs_mode));
+        }
 
         // Set SPI mode
         let phase = config.mode.phase == Phase::CaptureOnSecondTransition;
         let polarity = config.mode.polarity == Polarity::IdleHigh;
-        self.spi
-            .sckmode
-            .write(|w| w.pha().bit(phase).pol().bit(polarity));
-
-        self.spi.fmt.write(|w| unsafe {
-            w.proto().single();
-            w.endian().big(); // Transmit most-significant bit (MSB) first
-            w.dir().rx();
-            w.len().bits(8)
-        });
+        let sckmode = self.spi.sckmode.read();
+        if sckmode.pha().bit() != phase || sckmode.pol().bit() != polarity {
+            self.spi
+                .sckmode
+                .write(|w| w.pha().bit(phase).pol().bit(polarity));
+        }
+
+        assert!(
+            data_lines(config.protocol) <= PINS::DATA_LINES,
+            "SPI protocol needs more data lines than wired"
+        );
+        let fmt = self.spi.fmt.read();
+        if fmt.proto().variant() != Some(config.protocol)
+            || fmt.endian().variant() != config.bit_order
+            || fmt.dir().variant() != config.direction
+            || fmt.len().bits() != 8
+        {
+            self.spi.fmt.write(|w| unsafe {
+                w.proto().variant(config.protocol);
+                w.endian().variant(config.bit_order);
+                w.dir().variant(config.direction);
+                w.len().bits(8)
+            });
+        }
 
         // Set watermark levels
-        self.spi
-            .txmark
-            .write(|w| unsafe { w.txmark().bits(config.txmark) });
-        self.spi
-            .rxmark
-            .write(|w| unsafe { w.rxmark().bits(config.rxmark) });
+        if self.spi.txmark.read().txmark().bits() != config.txmark {
+            self.spi
+                .txmark
+                .write(|w| unsafe { w.txmark().bits(config.txmark) });
+        }
+        if self.spi.rxmark.read().rxmark().bits() != config.rxmark {
+            self.spi
+                .rxmark
+                .write(|w| unsafe { w.rxmark().bits(config.rxmark) });
+        }
 
         // set delays
-        self.spi.delay0.write(|w| unsafe {
-            w.cssck().bits(config.delays.cssck); // delay between assert and clock
-            w.sckcs().bits(config.delays.sckcs) // delay between clock and de-assert
-        });
-        self.spi.delay1.write(|w| unsafe {
-            w.intercs().bits(config.delays.intercs); // delay between CS re-assets
-            w.interxfr().bits(config.delays.interxfr) // intra-frame delay without CS re-asserts
-        });
+        let delay0 = self.spi.delay0.read();
+        if delay0.cssck().bits() != config.delays.cssck
+            || delay0.sckcs().bits() != config.delays.sckcs
+        {
+            self.spi.delay0.write(|w| unsafe {
+                w.cssck().bits(config.delays.cssck); // delay between assert and clock
+                w.sckcs().bits(config.delays.sckcs) // delay between clock and de-assert
+            });
+        }
+        let delay1 = self.spi.delay1.read();
+        if delay1.intercs().bits() != config.delays.intercs
+            || delay1.interxfr().bits() != config.delays.interxfr
+        {
+            self.spi.delay1.write(|w| unsafe {
+                w.intercs().bits(config.delays.intercs); // delay between CS re-assets
+                w.interxfr().bits(config.delays.interxfr) // intra-frame delay without CS re-asserts
+            });
+        }
 
         self.end_frame(); // ensure CS is de-asserted before we begin
     }
 
-    fn wait_for_rxfifo(&self) {
-        // Ensure that RX FIFO is empty
-        while self.spi.rxdata.read().empty().bit_is_clear() {}
+    /// Drains and discards stale RX FIFO entries, unless disabled in the [SpiConfig]
+    pub(crate) fn flush_rx_fifo(&mut self) {
+        if self.flush_rx {
+            // Every read pops an entry, so this returns once the FIFO is empty
+            while self.spi.rxdata.read().empty().bit_is_clear() {}
+            self.unread = 0;
+        }
+    }
+
+    /// Waits for and discards the bytes received for unread `FullDuplex` sends
+    pub(crate) fn discard_unread(&mut self) {
+        // Every frame sent in the RX direction ends with a byte in the RX FIFO
+        while self.unread > 0 {
+            if self.spi.rxdata.read().empty().bit_is_clear() {
+                self.unread -= 1;
+            }
+        }
+    }
+
+    /// Returns how many frames may be in flight without overflowing the RX FIFO,
+    /// which also holds the bytes received for unread `FullDuplex` sends
+    pub(crate) fn rx_room(&self) -> Result<usize, Error> {
+        match FIFO_DEPTH.saturating_sub(self.unread) {
+            0 => Err(Error::Overrun),
+            room => Ok(room),
+        }
+    }
+
+    /// Returns true if the controller doesn't fill the RX FIFO
+    pub(crate) fn is_tx_only(&self) -> bool {
+        self.spi.fmt.read().dir().is_tx()
+    }
+
+    /// Writes `words` with the TX direction, i.e. without any RX FIFO handling
+    fn write_tx_only<I>(&mut self, words: I)
+    where
+        I: Iterator<Item = u8>,
+    {
+        // Raise the TX watermark once the FIFO is empty
+        let txmark = self.spi.txmark.read().bits();
+        self.spi.txmark.write(|w| unsafe { w.txmark().bits(1) });
+
+        for byte in words {
+            while self.spi.txdata.read().full().bit_is_set() {}
+            self.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
+        }
+        while self.spi.ip.read().txwm().bit_is_clear() {}
+
+        self.spi.txmark.write(|w| unsafe { w.bits(txmark) });
+
+        // There are no received bytes telling when the last frame is shifted out,
+        // wait for it and the CS hold time (tlclk is the same as coreclk)
+        let sck_cycles = 2 * (self.spi.sckdiv.read().div().bits() as u32 + 1);
+        let sckcs = self.spi.delay0.read().sckcs().bits() as u32;
+        unsafe { riscv::asm::delay((8 + sckcs) * sck_cycles) };
+    }
+
+    /// Sets a callback invoked with `(sent, received)` for every byte exchanged
+    /// by `transfer`, `write` and `read_into`, or removes it with `None`
+    ///
+    /// The callback runs inside the transfer loop, inside the critical section
+    /// of shared devices. Transfers never have more bytes in flight than the RX
+    /// FIFO holds, so a slow callback, e.g. writing to a UART, loses no data but
+    /// stalls the bus between bytes and changes its timing. Push the bytes to a
+    /// buffer and dump them afterwards to keep the timing of the untraced bus.
+    /// `write_iter`, TX-only writes and streaming writes are not traced.
+    #[cfg(feature = "spi-trace")]
+    pub fn set_trace(&mut self, trace: Option<fn(u8, u8)>) {
+        self.trace = trace;
+    }
+
+    /// Reports an exchanged byte to the trace callback, if any
+    #[inline(always)]
+    fn trace(&self, _sent: u8, _received: u8) {
+        #[cfg(feature = "spi-trace")]
+        if let Some(trace) = self.trace {
+            trace(_sent, _received);
+        }
     }
 
     /// Starts frame by flagging CS assert, unless CSMODE = OFF
     pub(crate) fn start_frame(&mut self) {
+        self.assert_no_stream();
         if !self.spi.csmode.read().mode().is_off() {
             self.spi.csmode.write(|w| w.mode().hold());
         }
@@ -103,36 +408,51 @@ where
 
     // ex-traits now only accessible via devices
 
-    pub(crate) fn read(&mut self) -> nb::Result<u8, Infallible> {
+    pub(crate) fn read(&mut self) -> nb::Result<u8, Error> {
+        self.assert_no_stream();
         let rxdata = self.spi.rxdata.read();
 
         if rxdata.empty().bit_is_set() {
             Err(nb::Error::WouldBlock)
         } else {
+            self.unread = self.unread.saturating_sub(1);
             Ok(rxdata.data().bits())
         }
     }
 
-    pub(crate) fn send(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+    pub(crate) fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+        self.assert_no_stream();
         let txdata = self.spi.txdata.read();
 
-        if txdata.full().bit_is_set() {
+        if !self.is_tx_only() && self.unread >= FIFO_DEPTH {
+            // The received byte would be dropped
+            Err(nb::Error::Other(Error::Overrun))
+        } else if txdata.full().bit_is_set() {
             Err(nb::Error::WouldBlock)
         } else {
             self.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
+            if !self.is_tx_only() {
+                self.unread += 1;
+            }
             Ok(())
         }
     }
 
-    pub(crate) fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+    pub(crate) fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Error> {
+        assert!(!self.is_tx_only(), "SPI reads need the RX direction");
+
         let mut iwrite = 0;
         let mut iread = 0;
 
         // Ensure that RX FIFO is empty
-        self.wait_for_rxfifo();
+        self.flush_rx_fifo();
+        let room = self.rx_room()?;
 
         while iwrite < words.len() || iread < words.len() {
-            if iwrite < words.len() && self.spi.txdata.read().full().bit_is_clear() {
+            if iwrite < words.len()
+                && iwrite - iread < room
+                && self.spi.txdata.read().full().bit_is_clear()
+            {
                 let byte = unsafe { words.get_unchecked(iwrite) };
                 iwrite += 1;
                 self.spi.txdata.write(|w| unsafe { w.data().bits(*byte) });
@@ -141,6 +461,7 @@ where
             if iread < iwrite {
                 let data = self.spi.rxdata.read();
                 if data.empty().bit_is_clear() {
+                    self.trace(words[iread], data.data().bits());
                     unsafe { *words.get_unchecked_mut(iread) = data.data().bits() };
                     iread += 1;
                 }
@@ -150,15 +471,24 @@ where
         Ok(words)
     }
 
-    pub(crate) fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+    pub(crate) fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        if self.is_tx_only() {
+            self.write_tx_only(words.iter().copied());
+            return Ok(());
+        }
+
         let mut iwrite = 0;
         let mut iread = 0;
 
         // Ensure that RX FIFO is empty
-        self.wait_for_rxfifo();
+        self.flush_rx_fifo();
+        let room = self.rx_room()?;
 
         while iwrite < words.len() || iread < words.len() {
-            if iwrite < words.len() && self.spi.txdata.read().full().bit_is_clear() {
+            if iwrite < words.len()
+                && iwrite - iread < room
+                && self.spi.txdata.read().full().bit_is_clear()
+            {
                 let byte = unsafe { words.get_unchecked(iwrite) };
                 iwrite += 1;
                 self.spi.txdata.write(|w| unsafe { w.data().bits(*byte) });
@@ -166,7 +496,229 @@ where
 
             if iread < iwrite {
                 // Read and discard byte, if any
-                if self.spi.rxdata.read().empty().bit_is_clear() {
+                let data = self.spi.rxdata.read();
+                if data.empty().bit_is_clear() {
+                    self.trace(words[iread], data.data().bits());
+                    iread += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clocks out `count` zero bytes and passes each received byte to `f` as it arrives
+    pub(crate) fn read_into<F>(&mut self, count: usize, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(u8),
+    {
+        assert!(!self.is_tx_only(), "SPI reads need the RX direction");
+
+        let mut iwrite = 0;
+        let mut iread = 0;
+
+        // Ensure that RX FIFO is empty
+        self.flush_rx_fifo();
+        let room = self.rx_room()?;
+
+        while iwrite < count || iread < count {
+            if iwrite < count
+                && iwrite - iread < room
+                && self.spi.txdata.read().full().bit_is_clear()
+            {
+                iwrite += 1;
+                self.spi.txdata.write(|w| unsafe { w.data().bits(0) });
+            }
+
+            if iread < iwrite {
+                let data = self.spi.rxdata.read();
+                if data.empty().bit_is_clear() {
+                    self.trace(0, data.data().bits());
+                    f(data.data().bits());
+                    iread += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clocks out `count` dummy bytes, discarding the received ones
+    pub(crate) fn dummy_bytes(&mut self, count: u8) -> Result<(), Error> {
+        self.write_iter(core::iter::repeat(0).take(count as usize))
+    }
+
+    /// Writes `command`, clocks out `dummy` bytes and then reads into `read`
+    pub(crate) fn write_dummy_read(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        read: &mut [u8],
+    ) -> Result<(), Error> {
+        self.write(command)?;
+        self.dummy_bytes(dummy)?;
+
+        let mut iter = read.iter_mut();
+        self.read_into(iter.len(), |byte| {
+            if let Some(slot) = iter.next() {
+                *slot = byte;
+            }
+        })
+    }
+
+    /// Like [write_dummy_read](Self::write_dummy_read), but reads the response over
+    /// the data lines of `protocol`, e.g. for quad output flash reads
+    pub(crate) fn write_dummy_read_wide(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        protocol: PROTO_A,
+        read: &mut [u8],
+    ) -> Result<(), Error>
+    where
+        PINS: Pins<SPI>,
+    {
+        assert!(
+            data_lines(protocol) <= PINS::DATA_LINES,
+            "SPI protocol needs more data lines than wired"
+        );
+        self.write(command)?;
+        self.dummy_bytes(dummy)?;
+
+        // All previous frames are complete, switch the format for the data phase
+        let fmt = self.spi.fmt.read().bits();
+        self.spi.fmt.modify(|_, w| {
+            w.proto().variant(protocol);
+            w.dir().rx()
+        });
+        let mut iter = read.iter_mut();
+        let result = self.read_into(iter.len(), |byte| {
+            if let Some(slot) = iter.next() {
+                *slot = byte;
+            }
+        });
+        self.spi.fmt.write(|w| unsafe { w.bits(fmt) });
+
+        result
+    }
+
+    /// Writes `word` as four 8-bit frames, most significant byte first
+    pub(crate) fn write_u32_be(&mut self, word: u32) -> Result<(), Error> {
+        self.write(&word.to_be_bytes())
+    }
+
+    /// Transfers `word` as four 8-bit frames, most significant byte first,
+    /// and returns the word assembled from the received bytes
+    pub(crate) fn transfer_u32_be(&mut self, word: u32) -> Result<u32, Error> {
+        let mut bytes = word.to_be_bytes();
+        self.transfer(&mut bytes)?;
+
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    /// Writes each of `words` as two 8-bit frames, most significant byte first
+    pub(crate) fn write_u16_be(&mut self, words: &[u16]) -> Result<(), Error> {
+        self.write_iter(words.iter().flat_map(|word| word.to_be_bytes()))
+    }
+
+    /// Transfers each of `words` as two 8-bit frames, most significant byte first,
+    /// replacing it with the word assembled from the received bytes
+    pub(crate) fn transfer_u16_be(&mut self, words: &mut [u16]) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            let mut bytes = word.to_be_bytes();
+            self.transfer(&mut bytes)?;
+            *word = u16::from_be_bytes(bytes);
+        }
+
+        Ok(())
+    }
+
+    /// Writes `words` and checks that every received byte equals the sent one.
+    /// Returns the index of the first mismatching byte, if any.
+    pub(crate) fn transfer_verify(&mut self, words: &[u8]) -> Result<(), usize> {
+        assert!(!self.is_tx_only(), "SPI reads need the RX direction");
+
+        let mut iwrite = 0;
+        let mut iread = 0;
+        let mut mismatch = None;
+
+        // Ensure that RX FIFO is empty, the echo of every byte is checked
+        self.discard_unread();
+        self.flush_rx_fifo();
+        let room = FIFO_DEPTH;
+
+        while iwrite < words.len() || iread < words.len() {
+            if iwrite < words.len()
+                && iwrite - iread < room
+                && self.spi.txdata.read().full().bit_is_clear()
+            {
+                let byte = unsafe { words.get_unchecked(iwrite) };
+                iwrite += 1;
+                self.spi.txdata.write(|w| unsafe { w.data().bits(*byte) });
+            }
+
+            if iread < iwrite {
+                let data = self.spi.rxdata.read();
+                if data.empty().bit_is_clear() {
+                    self.trace(words[iread], data.data().bits());
+                    if mismatch.is_none() && data.data().bits() != words[iread] {
+                        mismatch = Some(iread);
+                    }
+                    iread += 1;
+                }
+            }
+        }
+
+        match mismatch {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+
+    /// Exchanges `max(read.len(), write.len())` frames, sending zeros past the end
+    /// of `write` and discarding the bytes past the end of `read`, and gives up
+    /// once `max_ticks` mtime ticks have elapsed
+    ///
+    /// Returns [Error::Timeout] with the number of frames received on timeout.
+    pub(crate) fn transfer_with_timeout(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+        max_ticks: u64,
+    ) -> Result<(), Error> {
+        assert!(!self.is_tx_only(), "SPI reads need the RX direction");
+
+        let deadline = MTIME.mtime() + max_ticks;
+        let len = read.len().max(write.len());
+        let mut iwrite = 0;
+        let mut iread = 0;
+
+        // Ensure that RX FIFO is empty
+        self.flush_rx_fifo();
+        // Times out if unread `FullDuplex` bytes leave no room
+        let room = self.rx_room().unwrap_or(0);
+
+        while iwrite < len || iread < len {
+            if MTIME.mtime() >= deadline {
+                // The replies of the frames in flight are left in the RX FIFO
+                self.unread += iwrite - iread;
+                return Err(Error::Timeout { received: iread });
+            }
+
+            if iwrite < len && iwrite - iread < room && self.spi.txdata.read().full().bit_is_clear()
+            {
+                let byte = write.get(iwrite).copied().unwrap_or(0);
+                iwrite += 1;
+                self.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
+            }
+
+            if iread < iwrite {
+                let data = self.spi.rxdata.read();
+                if data.empty().bit_is_clear() {
+                    self.trace(write.get(iread).copied().unwrap_or(0), data.data().bits());
+                    if let Some(slot) = read.get_mut(iread) {
+                        *slot = data.data().bits();
+                    }
                     iread += 1;
                 }
             }
@@ -175,20 +727,26 @@ where
         Ok(())
     }
 
-    pub(crate) fn write_iter<WI>(&mut self, words: WI) -> Result<(), Infallible>
+    pub(crate) fn write_iter<WI>(&mut self, words: WI) -> Result<(), Error>
     where
         WI: IntoIterator<Item = u8>,
     {
+        if self.is_tx_only() {
+            self.write_tx_only(words.into_iter());
+            return Ok(());
+        }
+
         let mut iter = words.into_iter();
 
         let mut read_count = 0;
         let mut has_data = true;
 
         // Ensure that RX FIFO is empty
-        self.wait_for_rxfifo();
+        self.flush_rx_fifo();
+        let room = self.rx_room()?;
 
         while has_data || read_count > 0 {
-            if has_data && self.spi.txdata.read().full().bit_is_clear() {
+            if has_data && read_count < room && self.spi.txdata.read().full().bit_is_clear() {
                 if let Some(byte) = iter.next() {
                     self.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
                     read_count += 1;
@@ -208,10 +766,98 @@ where
         Ok(())
     }
 
-    pub(crate) fn exec<'op>(
-        &mut self,
-        operations: &mut [Operation<'op, u8>],
-    ) -> Result<(), Infallible> {
+    /// Starts an interrupt-driven streaming write of `buf`
+    pub(crate) fn start_stream(&mut self, buf: &'static [u8]) {
+        assert!(!self.is_tx_only(), "SPI reads need the RX direction");
+
+        // Ensure that RX FIFO is empty, the stream has no use for stale bytes
+        self.discard_unread();
+        self.flush_rx_fifo();
+        let room = FIFO_DEPTH;
+
+        // The stream only completes through the RX watermark interrupt, which
+        // must fire as long as any received byte is left to discard
+        let rxmark = self.spi.rxmark.read().rxmark().bits();
+        self.spi.rxmark.write(|w| unsafe { w.rxmark().bits(0) });
+
+        self.start_frame();
+        self.stream = Some(Stream {
+            current: buf,
+            next: None,
+            pending: 0,
+            room,
+            rxmark,
+        });
+        self.spi.ie.write(|w| w.txwm().set_bit().rxwm().set_bit());
+    }
+
+    /// Queues `buf` to be streamed right after the current buffer
+    pub(crate) fn stream_next(&mut self, buf: &'static [u8]) -> Result<(), &'static [u8]> {
+        match self.stream.as_mut() {
+            None => {
+                self.start_stream(buf);
+                Ok(())
+            }
+            Some(stream) if stream.next.is_none() => {
+                stream.next = Some(buf);
+                self.spi.ie.modify(|_, w| w.txwm().set_bit());
+                Ok(())
+            }
+            Some(_) => Err(buf),
+        }
+    }
+
+    /// Returns true when no streaming write is in progress
+    pub(crate) fn is_stream_done(&self) -> bool {
+        self.stream.is_none()
+    }
+
+    /// Feeds the TX FIFO and drains the RX FIFO of an ongoing streaming write
+    pub(crate) fn handle_stream_interrupt(&mut self) {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        // Read and discard received bytes, if any
+        while stream.pending > 0 && self.spi.rxdata.read().empty().bit_is_clear() {
+            stream.pending -= 1;
+        }
+
+        loop {
+            if stream.current.is_empty() {
+                match stream.next.take() {
+                    Some(next) => stream.current = next,
+                    None => break,
+                }
+            }
+            // Don't let more bytes be in flight than the RX FIFO can hold
+            if stream.pending >= stream.room || self.spi.txdata.read().full().bit_is_set() {
+                break;
+            }
+            let byte = stream.current[0];
+            stream.current = &stream.current[1..];
+            stream.pending += 1;
+            self.spi.txdata.write(|w| unsafe { w.data().bits(byte) });
+        }
+
+        if stream.current.is_empty() && stream.next.is_none() {
+            if stream.pending == 0 {
+                let rxmark = stream.rxmark;
+                self.spi.ie.reset();
+                self.spi
+                    .rxmark
+                    .write(|w| unsafe { w.rxmark().bits(rxmark) });
+                self.stream = None;
+                self.end_frame();
+            } else {
+                // Nothing left to send, wait for the remaining bytes to be shifted out
+                self.spi.ie.modify(|_, w| w.txwm().clear_bit());
+            }
+        }
+    }
+
+    pub(crate) fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Error> {
         for op in operations {
             match op {
                 Operation::Transfer(words) => {
@@ -232,6 +878,13 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
+    /// Applies `config` to the controller, e.g. before using the bus directly
+    /// through the `embedded-hal-async` [SpiBus](embedded_hal_async::spi::SpiBus) trait
+    #[cfg(feature = "async")]
+    pub fn set_config(&mut self, config: &SpiConfig) {
+        self.configure(config, PINS::CS_INDEX);
+    }
+
     /// Create a new [SpiExclusiveDevice] for exclusive use on this bus
     pub fn new_device(self, config: &SpiConfig) -> SpiExclusiveDevice<SPI, PINS> {
         SpiExclusiveDevice::new(self, config)
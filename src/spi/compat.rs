@@ -0,0 +1,134 @@
+//! Backward compatibility with the `Infallible` SPI errors
+#![allow(deprecated)]
+
+use core::convert::Infallible;
+
+use embedded_hal::blocking::spi::{Transfer, Write, WriteIter};
+use embedded_hal::spi::FullDuplex;
+use riscv::interrupt;
+
+use super::{Error, PinCS, Pins, SpiExclusiveDevice, SpiSharedDevice, SpiX};
+
+/// Wraps an SPI device so that it reports `Infallible` errors, as before [Error]
+///
+/// Like the controller used to do when its RX FIFO was full, the wrapper drops
+/// the replies of `FullDuplex::send` that were never read instead of reporting
+/// [Error::Overrun]: send-only drivers keep working, and the blocking `Transfer`,
+/// `Write` and `WriteIter` implementations discard them before starting.
+#[deprecated(
+    note = "Please handle spi::Error instead, this wrapper will be removed in the next release"
+)]
+pub struct InfallibleSpi<D>(pub D);
+
+impl<D> InfallibleSpi<D> {
+    /// Releases the wrapped device
+    pub fn release(self) -> D {
+        self.0
+    }
+}
+
+/// SPI devices whose unread `FullDuplex` replies can be discarded
+#[doc(hidden)]
+pub trait DiscardUnread {
+    /// Waits for and discards the bytes received for unread `FullDuplex` sends
+    fn discard_unread(&mut self);
+}
+
+impl<SPI, PINS> DiscardUnread for SpiExclusiveDevice<SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    fn discard_unread(&mut self) {
+        self.bus.discard_unread();
+    }
+}
+
+impl<SPI, PINS, CS> DiscardUnread for SpiSharedDevice<'_, SPI, PINS, CS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+    CS: PinCS<SPI>,
+{
+    fn discard_unread(&mut self) {
+        interrupt::free(|| self.bus.borrow_mut().discard_unread());
+    }
+}
+
+impl<D> FullDuplex<u8> for InfallibleSpi<D>
+where
+    D: FullDuplex<u8, Error = Error> + DiscardUnread,
+{
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.0.read().map_err(|_| nb::Error::WouldBlock)
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        match self.0.send(byte) {
+            Ok(()) => Ok(()),
+            Err(nb::Error::Other(_)) => {
+                // Make room for the reply, the next attempt goes through
+                self.0.discard_unread();
+                Err(nb::Error::WouldBlock)
+            }
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+// The blocking operations fail before sending anything when unread replies leave
+// no room in the RX FIFO, so they are simply retried once these are discarded
+
+impl<D> Transfer<u8> for InfallibleSpi<D>
+where
+    D: Transfer<u8, Error = Error> + DiscardUnread,
+{
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+        loop {
+            self.0.discard_unread();
+            if self.0.transfer(words).is_ok() {
+                return Ok(words);
+            }
+        }
+    }
+}
+
+impl<D> Write<u8> for InfallibleSpi<D>
+where
+    D: Write<u8, Error = Error> + DiscardUnread,
+{
+    type Error = Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        loop {
+            self.0.discard_unread();
+            if self.0.write(words).is_ok() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<D> WriteIter<u8> for InfallibleSpi<D>
+where
+    D: WriteIter<u8, Error = Error> + DiscardUnread,
+{
+    type Error = Infallible;
+
+    fn write_iter<WI>(&mut self, words: WI) -> Result<(), Infallible>
+    where
+        WI: IntoIterator<Item = u8>,
+    {
+        let mut words = words.into_iter();
+        loop {
+            self.0.discard_unread();
+            if self.0.write_iter(&mut words).is_ok() {
+                return Ok(());
+            }
+        }
+    }
+}
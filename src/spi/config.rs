@@ -1,7 +1,15 @@
-use e310x::qspi0::csmode::MODE_A;
-use embedded_hal::spi::Mode;
+use core::fmt;
 
-use crate::{clock::Clocks, time::Hertz};
+use e310x::qspi0::{
+    csmode::MODE_A,
+    fmt::{DIR_A, ENDIAN_A, PROTO_A},
+};
+use embedded_hal::spi::{Mode, Phase, Polarity};
+
+use crate::{
+    clock::Clocks,
+    time::{duration_to_ticks, Hertz, Microseconds, RateError},
+};
 
 /// SPI Bus configuration
 
@@ -12,6 +20,8 @@ pub struct SpiConfig {
     pub mode: Mode,
     /// Clock Divisor calculated from frozen core clock frequency and SPI frequency
     pub(crate) clock_divisor: u32,
+    /// Requested SPI frequency
+    pub(crate) freq: Hertz,
     /// CS Mode
     pub cs_mode: MODE_A,
     /// Watermark level for transmits
@@ -20,9 +30,47 @@ pub struct SpiConfig {
     pub rxmark: u8,
     /// Configuration values for CS and SCK related delays
     pub delays: SpiDelayConfig,
+    /// Minimum time CS stays de-asserted after a transaction
+    pub(crate) post_transaction_delay: Microseconds,
+    /// Discard stale RX FIFO entries before each transfer
+    pub(crate) flush_rx_on_start: bool,
+    /// I/O direction
+    pub(crate) direction: DIR_A,
+    /// Number of data lines
+    pub(crate) protocol: PROTO_A,
+    /// CS is asserted high instead of low
+    pub(crate) cs_active_high: bool,
+    /// Bit order of the frames
+    pub(crate) bit_order: ENDIAN_A,
 }
 
-#[derive(Clone)]
+impl fmt::Debug for SpiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match (self.mode.polarity, self.mode.phase) {
+            (Polarity::IdleLow, Phase::CaptureOnFirstTransition) => "MODE_0",
+            (Polarity::IdleLow, Phase::CaptureOnSecondTransition) => "MODE_1",
+            (Polarity::IdleHigh, Phase::CaptureOnFirstTransition) => "MODE_2",
+            (Polarity::IdleHigh, Phase::CaptureOnSecondTransition) => "MODE_3",
+        };
+        f.debug_struct("SpiConfig")
+            .field("mode", &format_args!("{}", mode))
+            .field("freq", &self.freq)
+            .field("clock_divisor", &self.clock_divisor)
+            .field("cs_mode", &self.cs_mode)
+            .field("txmark", &self.txmark)
+            .field("rxmark", &self.rxmark)
+            .field("delays", &self.delays)
+            .field("post_transaction_delay", &self.post_transaction_delay)
+            .field("flush_rx_on_start", &self.flush_rx_on_start)
+            .field("direction", &self.direction)
+            .field("protocol", &self.protocol)
+            .field("cs_active_high", &self.cs_active_high)
+            .field("bit_order", &self.bit_order)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
 /// Configuration values for CS and SCK related delays
 pub struct SpiDelayConfig {
     /// delay between assert and clock in clock ticks
@@ -37,24 +85,192 @@ pub struct SpiDelayConfig {
 
 impl SpiConfig {
     /// Create new default configuration with given [Mode] and frequency using core [Clocks]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `freq` can't be produced from `tlclk`, see [SpiConfig::try_new].
     pub fn new(mode: Mode, freq: Hertz, clocks: &Clocks) -> Self {
-        let clock_divisor = clocks.tlclk().0 / (2 * freq.0) - 1;
-        assert!(clock_divisor <= 0xfff);
+        Self::try_new(mode, freq, clocks).expect("unachievable SPI frequency")
+    }
 
-        Self {
+    /// Create new default configuration with given [Mode] and frequency using core [Clocks]
+    ///
+    /// Returns an error if `freq` is above `tlclk / 2` or below `tlclk / 8192`.
+    pub fn try_new(mode: Mode, freq: Hertz, clocks: &Clocks) -> Result<Self, RateError> {
+        Ok(Self {
             mode,
-            clock_divisor,
+            clock_divisor: clock_divisor(freq, clocks)?,
+            freq,
             cs_mode: MODE_A::HOLD,
             txmark: 1,
             rxmark: 0,
             delays: SpiDelayConfig::default(),
-        }
+            post_transaction_delay: Microseconds(0),
+            flush_rx_on_start: true,
+            direction: DIR_A::RX,
+            protocol: PROTO_A::SINGLE,
+            cs_active_high: false,
+            bit_order: ENDIAN_A::BIG,
+        })
     }
 
     /// Calculated clock divisor
     pub fn clock_divisor(&self) -> u32 {
         self.clock_divisor
     }
+
+    /// Recalculates the clock divisor for the requested frequency after the
+    /// core clock frequency has changed
+    ///
+    /// The divisor is left untouched if the frequency can't be produced from the
+    /// new `tlclk`.
+    pub fn reclock(&mut self, clocks: &Clocks) -> Result<(), RateError> {
+        self.clock_divisor = clock_divisor(self.freq, clocks)?;
+        Ok(())
+    }
+
+    /// Changes the requested SPI frequency, leaving the configuration untouched
+    /// if `freq` can't be produced from `tlclk` (see [SpiConfig::try_new])
+    pub fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks) -> Result<(), RateError> {
+        self.clock_divisor = clock_divisor(freq, clocks)?;
+        self.freq = freq;
+        Ok(())
+    }
+
+    /// Sets the minimum time CS stays de-asserted after each transaction
+    ///
+    /// Unlike [SpiDelayConfig::intercs], which is counted in SCK cycles, this delay
+    /// is measured with `mtime` and enforced by the device wrappers before the next
+    /// transaction asserts CS. The `mtime` resolution is ~30.5 µs, so the actual
+    /// delay may be up to one tick longer than requested.
+    pub fn post_transaction_delay(mut self, delay: Microseconds) -> Self {
+        self.post_transaction_delay = delay;
+        self
+    }
+
+    /// Sets whether stale RX FIFO entries are drained and discarded before each transfer
+    ///
+    /// Enabled by default. When disabled, bytes left over by a previous operation
+    /// are returned as the first bytes read by the next transfer.
+    pub fn flush_rx_on_start(mut self, flush: bool) -> Self {
+        self.flush_rx_on_start = flush;
+        self
+    }
+
+    /// Sets the I/O direction, [DIR_A::RX] by default
+    ///
+    /// With [DIR_A::TX] the controller doesn't fill the RX FIFO, so writes don't
+    /// need to drain it, which suits write-only devices such as displays. Reads
+    /// and transfers need [DIR_A::RX] and panic otherwise, so use a separate
+    /// configuration (e.g. another device on a shared bus) for them.
+    pub fn direction(mut self, direction: DIR_A) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the SPI protocol, [PROTO_A::SINGLE] by default
+    ///
+    /// With [PROTO_A::DUAL] and [PROTO_A::QUAD] each frame is exchanged over 2 or 4
+    /// data lines, driven by the controller with [DIR_A::TX] and by the device with
+    /// [DIR_A::RX]. Dual needs both MOSI and MISO and quad is only wired on QSPI0, the
+    /// [SpiBus](super::SpiBus) panics when configured for more lines than its pins have.
+    pub fn protocol(mut self, protocol: PROTO_A) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets the bit order of the frames, [ENDIAN_A::BIG] (MSB first) by default
+    ///
+    /// Use [ENDIAN_A::LITTLE] for devices clocking the LSB first.
+    pub fn bit_order(mut self, bit_order: ENDIAN_A) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Sets the delay between CS assertion and the first SCK edge, in SCK cycles (1 by default)
+    pub fn cs_to_sck_delay(mut self, cycles: u8) -> Self {
+        self.delays.cssck = cycles;
+        self
+    }
+
+    /// Sets the delay between the last SCK edge and CS de-assertion, in SCK cycles (1 by default)
+    pub fn sck_to_cs_delay(mut self, cycles: u8) -> Self {
+        self.delays.sckcs = cycles;
+        self
+    }
+
+    /// Sets the minimum time CS stays de-asserted between transactions, in SCK cycles
+    /// (1 by default), see also [post_transaction_delay](Self::post_transaction_delay)
+    pub fn inter_cs_delay(mut self, cycles: u8) -> Self {
+        self.delays.intercs = cycles;
+        self
+    }
+
+    /// Sets the delay between consecutive frames without CS de-assertion, in SCK cycles
+    /// (0 by default)
+    pub fn inter_frame_delay(mut self, cycles: u8) -> Self {
+        self.delays.interxfr = cycles;
+        self
+    }
+
+    /// Sets whether CS is asserted high, for devices with an active-high chip select
+    ///
+    /// CS is active low by default. This only applies to the CS pins driven by the
+    /// controller, not to CS pins toggled by software such as [ManualCs](super::ManualCs).
+    pub fn cs_active_high(mut self, active_high: bool) -> Self {
+        self.cs_active_high = active_high;
+        self
+    }
+
+    /// Post-transaction delay in `mtime` ticks, rounded up so that the
+    /// requested time is always met
+    pub(crate) fn post_transaction_ticks(&self) -> u64 {
+        match self.post_transaction_delay.0 {
+            0 => 0,
+            // +1 as the first tick may already be partially elapsed
+            _ => duration_to_ticks(self.post_transaction_delay) + 1,
+        }
+    }
+}
+
+/// Clock divisor for an SPI frequency of `freq`
+pub(crate) fn clock_divisor(freq: Hertz, clocks: &Clocks) -> Result<u32, RateError> {
+    if freq.0 == 0 {
+        return Err(RateError::TooSlow);
+    }
+    match (clocks.tlclk().0 as u64 / (2 * freq.0 as u64)) as u32 {
+        0 => Err(RateError::TooFast),
+        half_periods if half_periods > 0x1000 => Err(RateError::TooSlow),
+        half_periods => Ok(half_periods - 1),
+    }
+}
+
+/// Returns the SCK divisor giving the highest frequency that does not exceed
+/// `target` when the SPI controller runs from `tlclk`
+///
+/// The SCK frequency is `tlclk / (2 * (div + 1))`.
+///
+/// # Panics
+///
+/// Panics if `target` is zero or lower than the slowest reachable frequency.
+///
+/// ```
+/// use e310x_hal::{spi::closest_divisor, time::U32Ext};
+///
+/// assert_eq!(closest_divisor(16.mhz().into(), 1.mhz().into()), 7);
+/// // 2.67 MHz, not 4 MHz
+/// assert_eq!(closest_divisor(16.mhz().into(), 3.mhz().into()), 2);
+/// // Capped at tlclk / 2
+/// assert_eq!(closest_divisor(16.mhz().into(), 10.mhz().into()), 0);
+/// assert_eq!(closest_divisor(320.mhz().into(), 50.mhz().into()), 3);
+/// assert_eq!(closest_divisor(320.mhz().into(), 40.khz().into()), 3999);
+/// ```
+pub fn closest_divisor(tlclk: Hertz, target: Hertz) -> u32 {
+    assert!(target.0 > 0);
+    let target2 = 2 * target.0 as u64;
+    let div = ((tlclk.0 as u64 + target2 - 1) / target2) as u32 - 1;
+    assert!(div <= 0xfff);
+    div
 }
 
 impl Default for SpiDelayConfig {
@@ -1,17 +1,26 @@
-use core::convert::Infallible;
-
 use embedded_hal::{
     blocking::spi::{Operation, Transactional, Transfer, Write, WriteIter},
     spi::FullDuplex,
 };
 
-use crate::spi::SpiConfig;
+use e310x::qspi0::{csmode::MODE_A, fmt::PROTO_A};
+
+use crate::{
+    clock::Clocks,
+    core::clint::MTIME,
+    spi::SpiConfig,
+    time::{Hertz, RateError},
+};
 
-use super::{Pins, SpiBus, SpiX};
+use super::{config::clock_divisor, BusState, Error, ManualCs, Pins, SpiBus, SpiX};
 
 /// SPI exclusive device abstraction
 pub struct SpiExclusiveDevice<SPI, PINS> {
-    bus: SpiBus<SPI, PINS>,
+    pub(crate) bus: SpiBus<SPI, PINS>,
+    freq: Hertz,
+    cs_active_high: bool,
+    post_transaction_ticks: u64,
+    ready_at: u64,
 }
 
 impl<SPI, PINS> SpiExclusiveDevice<SPI, PINS>
@@ -27,7 +36,287 @@ where
     {
         bus.configure(config, PINS::CS_INDEX);
 
-        Self { bus }
+        Self {
+            bus,
+            freq: config.freq,
+            cs_active_high: config.cs_active_high,
+            post_transaction_ticks: config.post_transaction_ticks(),
+            ready_at: 0,
+        }
+    }
+
+    /// Waits out the post-transaction delay and asserts CS
+    pub(crate) fn begin_transaction(&mut self) {
+        if self.post_transaction_ticks > 0 {
+            while MTIME.mtime() < self.ready_at {}
+        }
+        self.bus.start_frame();
+    }
+
+    /// De-asserts CS and records when the next transaction may start
+    pub(crate) fn end_transaction(&mut self) {
+        self.bus.end_frame();
+        if self.post_transaction_ticks > 0 {
+            self.ready_at = MTIME.mtime() + self.post_transaction_ticks;
+        }
+    }
+
+    /// Selects the hardware CS line used by subsequent transactions
+    ///
+    /// This allows a single exclusive owner to talk to several devices wired
+    /// to different CS lines. The CS polarity of the [SpiConfig] applies to the
+    /// new line.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instance has no CS line `cs_index`: QSPI1 has CS0 to CS3,
+    /// QSPI0 and QSPI2 only CS0.
+    pub fn select(&mut self, cs_index: u32) {
+        assert!(cs_index < SPI::CS_COUNT, "no such CS line");
+
+        self.bus.select_cs(cs_index, self.cs_active_high);
+    }
+
+    /// Reapplies the SPI frequency of the [SpiConfig] after the core clock
+    /// frequency has changed
+    ///
+    /// The divisor is left untouched if the frequency can't be produced from the
+    /// new `tlclk`.
+    pub fn reclock(&mut self, clocks: &Clocks) -> Result<(), RateError> {
+        self.set_frequency(self.freq, clocks)
+    }
+
+    /// Changes the SPI frequency, e.g. from the slow SD card initialization
+    /// clock to the fast data clock
+    ///
+    /// The frequency is left untouched if `freq` can't be produced from `tlclk`
+    /// (see [SpiConfig::try_new]). Later calls to [reclock](Self::reclock) keep `freq`.
+    pub fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks) -> Result<(), RateError> {
+        let div = clock_divisor(freq, clocks)?;
+        self.bus.set_clock_divisor(div);
+        self.freq = freq;
+        Ok(())
+    }
+
+    /// Runs `f` with the SCK frequency temporarily set to at most `freq`
+    /// (see [SpiBus::set_closest_frequency])
+    ///
+    /// `sckdiv` is rewritten before `f` runs and the previous frequency is restored
+    /// once `f` returns. `f` must not change the frequency itself: a
+    /// [set_frequency](Self::set_frequency) or [reclock](Self::reclock) done by `f`
+    /// is undone as well.
+    pub fn with_frequency<R, F>(&mut self, freq: Hertz, clocks: &Clocks, f: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        let (div, prev_freq) = (self.bus.clock_divisor(), self.freq);
+        self.bus.set_closest_frequency(freq, *clocks);
+
+        let result = f(self);
+
+        self.bus.set_clock_divisor(div);
+        self.freq = prev_freq;
+        result
+    }
+
+    /// Sets the transmit watermark (see [SpiBus::set_tx_watermark])
+    pub fn set_tx_watermark(&mut self, value: u8) {
+        self.bus.set_tx_watermark(value);
+    }
+
+    /// Sets the receive watermark (see [SpiBus::set_rx_watermark])
+    pub fn set_rx_watermark(&mut self, value: u8) {
+        self.bus.set_rx_watermark(value);
+    }
+
+    /// Returns the current CS mode of the controller (see [SpiBus::cs_mode])
+    pub fn cs_mode(&self) -> MODE_A {
+        self.bus.cs_mode()
+    }
+
+    /// Takes manual control of CS, see [ManualCs]
+    pub fn manual_cs(&mut self) -> ManualCs<'_, SPI, PINS> {
+        ManualCs::new(self)
+    }
+
+    /// Captures the current controller configuration (see [SpiBus::save_state])
+    pub fn save_state(&self) -> BusState {
+        self.bus.save_state()
+    }
+
+    /// Restores a controller configuration (see [SpiBus::restore_state])
+    pub fn restore_state(&mut self, state: &BusState) {
+        self.bus.restore_state(state);
+    }
+
+    /// Reads `count` bytes, passing each received byte to `f` as it arrives
+    ///
+    /// Zeros are sent while reading. `f` runs inside the transfer loop, so a slow
+    /// callback stalls the bus between bytes; no more bytes are sent than the RX
+    /// FIFO can hold, so none are lost.
+    pub fn read_into<F>(&mut self, count: usize, f: F) -> Result<(), Error>
+    where
+        F: FnMut(u8),
+    {
+        self.begin_transaction();
+        let result = self.bus.read_into(count, f);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Writes `command`, clocks out `dummy` bytes and reads the response into
+    /// `read`, with CS held asserted throughout
+    ///
+    /// This matches the command/dummy cycles/data sequence of e.g. flash fast reads.
+    /// Dummy bytes are sent as zeros and the bytes received meanwhile are discarded.
+    pub fn write_dummy_read(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        read: &mut [u8],
+    ) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self.bus.write_dummy_read(command, dummy, read);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Like [write_dummy_read](Self::write_dummy_read), but reads the response
+    /// over the data lines of `protocol`
+    ///
+    /// The command and dummy bytes are sent with the configured protocol, then the
+    /// controller switches to `protocol` in the RX direction for the data phase and
+    /// back afterwards. E.g. a flash quad output fast read (0x6B) on QSPI0 is
+    /// `write_dummy_read_wide(&[0x6B, a2, a1, a0], 1, PROTO_A::QUAD, buf)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protocol` needs more data lines than the pins of the bus have.
+    pub fn write_dummy_read_wide(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        protocol: PROTO_A,
+        read: &mut [u8],
+    ) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self
+            .bus
+            .write_dummy_read_wide(command, dummy, protocol, read);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Writes a 32-bit `word` for devices with frames wider than the controller's
+    ///
+    /// The word is sent as four 8-bit sub-frames, most significant byte first,
+    /// with CS held asserted across all of them.
+    pub fn write_u32_be(&mut self, word: u32) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self.bus.write_u32_be(word);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Transfers a 32-bit `word` for devices with frames wider than the controller's
+    ///
+    /// The word is sent as four 8-bit sub-frames, most significant byte first,
+    /// with CS held asserted across all of them. Returns the received word.
+    pub fn transfer_u32_be(&mut self, word: u32) -> Result<u32, Error> {
+        self.begin_transaction();
+        let result = self.bus.transfer_u32_be(word);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Writes 16-bit `words`, e.g. for displays and ADCs with 9 to 16-bit frames
+    ///
+    /// The controller frames are at most 8 bits long, so each word is sent as two
+    /// 8-bit sub-frames, most significant byte first, with CS held asserted across
+    /// all of them. Devices with shorter frames should ignore the leading bits.
+    pub fn write_u16_be(&mut self, words: &[u16]) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self.bus.write_u16_be(words);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Transfers 16-bit `words` in place, see [write_u16_be](Self::write_u16_be)
+    pub fn transfer_u16_be(&mut self, words: &mut [u16]) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self.bus.transfer_u16_be(words);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Writes `words` and checks that the bytes read back are the same,
+    /// e.g. with MOSI and MISO tied together.
+    ///
+    /// Returns the index of the first mismatching byte on failure. Bytes received
+    /// for unread `FullDuplex` sends are discarded first.
+    pub fn transfer_verify(&mut self, words: &[u8]) -> Result<(), usize> {
+        self.begin_transaction();
+        let result = self.bus.transfer_verify(words);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Transfers `write` while reading into `read`, giving up once `max_ticks`
+    /// mtime ticks have elapsed, e.g. when an unclocked device stalls the bus
+    ///
+    /// `max(read.len(), write.len())` frames are exchanged: zeros are sent past the
+    /// end of `write` and the bytes past the end of `read` are discarded. Returns
+    /// [Error::Timeout] with the number of frames received on timeout. Frames still in the TX FIFO at that
+    /// point are sent once the bus runs again, before the next transaction starts.
+    pub fn transfer_with_timeout(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+        max_ticks: u64,
+    ) -> Result<(), Error> {
+        self.begin_transaction();
+        let result = self.bus.transfer_with_timeout(read, write, max_ticks);
+        self.end_transaction();
+
+        result
+    }
+
+    /// Starts an interrupt-driven streaming write of `buf`
+    ///
+    /// CS is held asserted until the stream completes. The bytes are fed to the
+    /// TX FIFO by [handle_stream_interrupt](Self::handle_stream_interrupt), which must
+    /// be called from the interrupt handler of the SPI peripheral. Raising
+    /// [SpiConfig::txmark] reduces the gaps between bytes at the cost of more interrupts.
+    ///
+    /// Other transfers must not be performed on this device until the stream is done.
+    pub fn start_stream(&mut self, buf: &'static [u8]) {
+        self.bus.start_stream(buf);
+    }
+
+    /// Queues `buf` to be sent right after the buffer currently being streamed,
+    /// or starts a new stream if none is in progress.
+    ///
+    /// Returns `buf` back as an error if a buffer is already queued.
+    pub fn stream_next(&mut self, buf: &'static [u8]) -> Result<(), &'static [u8]> {
+        self.bus.stream_next(buf)
+    }
+
+    /// Returns true when all streamed bytes have been transmitted
+    pub fn is_stream_done(&self) -> bool {
+        self.bus.is_stream_done()
+    }
+
+    /// Progresses an ongoing streaming write. Call this from the SPI interrupt handler.
+    pub fn handle_stream_interrupt(&mut self) {
+        self.bus.handle_stream_interrupt();
     }
 
     /// Releases the Bus back deconstructing it
@@ -41,13 +330,13 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
-    fn read(&mut self) -> nb::Result<u8, Infallible> {
+    fn read(&mut self) -> nb::Result<u8, Error> {
         self.bus.read()
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
         self.bus.send(byte)
     }
 }
@@ -57,12 +346,12 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
-        self.bus.start_frame();
+        self.begin_transaction();
         let result = self.bus.transfer(words);
-        self.bus.end_frame();
+        self.end_transaction();
 
         result
     }
@@ -73,12 +362,12 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-        self.bus.start_frame();
+        self.begin_transaction();
         let result = self.bus.write(words);
-        self.bus.end_frame();
+        self.end_transaction();
 
         result
     }
@@ -89,15 +378,15 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
     where
         WI: IntoIterator<Item = u8>,
     {
-        self.bus.start_frame();
+        self.begin_transaction();
         let result = self.bus.write_iter(words);
-        self.bus.end_frame();
+        self.end_transaction();
 
         result
     }
@@ -108,12 +397,12 @@ where
     SPI: SpiX,
     PINS: Pins<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
-    fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Infallible> {
-        self.bus.start_frame();
+    fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Error> {
+        self.begin_transaction();
         let result = self.bus.exec(operations);
-        self.bus.end_frame();
+        self.end_transaction();
 
         result
     }
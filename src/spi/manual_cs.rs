@@ -0,0 +1,121 @@
+use embedded_hal::blocking::spi::{Operation, Transactional, Transfer, Write, WriteIter};
+
+use super::{Error, Pins, SpiExclusiveDevice, SpiX};
+
+/// Manual CS control over a [SpiExclusiveDevice]
+///
+/// CS stays as set by [assert](Self::assert) and [deassert](Self::deassert)
+/// across any number of transfers, which allows for CS framing that doesn't match
+/// single transactions (e.g. a multi-command sequence with computations in between).
+/// CS is de-asserted when this is dropped.
+///
+/// CS is driven through the controller's `csmode` register, so this has no effect
+/// if the configured CS mode is `OFF`: use a GPIO pin as CS in that case.
+pub struct ManualCs<'dev, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    device: &'dev mut SpiExclusiveDevice<SPI, PINS>,
+    asserted: bool,
+}
+
+impl<'dev, SPI, PINS> ManualCs<'dev, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    pub(crate) fn new(device: &'dev mut SpiExclusiveDevice<SPI, PINS>) -> Self {
+        Self {
+            device,
+            asserted: false,
+        }
+    }
+
+    /// Asserts CS, honoring the post-transaction delay of the previous transaction
+    pub fn assert(&mut self) {
+        if !self.asserted {
+            self.device.begin_transaction();
+            self.asserted = true;
+        }
+    }
+
+    /// De-asserts CS
+    pub fn deassert(&mut self) {
+        if self.asserted {
+            self.device.end_transaction();
+            self.asserted = false;
+        }
+    }
+
+    /// Returns true if CS is asserted
+    pub fn is_asserted(&self) -> bool {
+        self.asserted
+    }
+
+    /// Clocks out `count` dummy bytes, discarding the received ones
+    pub fn dummy_bytes(&mut self, count: u8) -> Result<(), Error> {
+        self.device.bus.dummy_bytes(count)
+    }
+}
+
+impl<SPI, PINS> Drop for ManualCs<'_, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    fn drop(&mut self) {
+        self.deassert();
+    }
+}
+
+impl<SPI, PINS> Transfer<u8> for ManualCs<'_, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    type Error = Error;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.device.bus.transfer(words)
+    }
+}
+
+impl<SPI, PINS> Write<u8> for ManualCs<'_, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    type Error = Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.device.bus.write(words)
+    }
+}
+
+impl<SPI, PINS> WriteIter<u8> for ManualCs<'_, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    type Error = Error;
+
+    fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
+    where
+        WI: IntoIterator<Item = u8>,
+    {
+        self.device.bus.write_iter(words)
+    }
+}
+
+impl<SPI, PINS> Transactional<u8> for ManualCs<'_, SPI, PINS>
+where
+    SPI: SpiX,
+    PINS: Pins<SPI>,
+{
+    type Error = Error;
+
+    fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Error> {
+        self.device.bus.exec(operations)
+    }
+}
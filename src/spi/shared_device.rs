@@ -1,18 +1,67 @@
-use core::convert::Infallible;
-
 use embedded_hal::{
     blocking::spi::{Operation, Transactional, Transfer, Write, WriteIter},
     spi::FullDuplex,
 };
 use riscv::interrupt;
 
-use super::{PinCS, Pins, PinsNoCS, SharedBus, SpiConfig, SpiX};
+use e310x::qspi0::fmt::PROTO_A;
+
+use crate::{
+    clock::Clocks,
+    core::clint::MTIME,
+    time::{Hertz, RateError},
+};
+
+use super::{Error, PinCS, Pins, PinsNoCS, SharedBus, SpiBus, SpiConfig, SpiX};
 
 /// SPI shared device abstraction
 pub struct SpiSharedDevice<'bus, SPI, PINS, CS> {
-    bus: &'bus SharedBus<SPI, PINS>,
+    pub(crate) bus: &'bus SharedBus<SPI, PINS>,
     cs: CS,
-    config: SpiConfig,
+    pub(crate) config: SpiConfig,
+    post_transaction_ticks: u64,
+    ready_at: u64,
+}
+
+impl<SPI, PINS, CS> SpiSharedDevice<'_, SPI, PINS, CS> {
+    /// Waits out the post-transaction delay of the previous transaction
+    pub(crate) fn wait_ready(&self) {
+        if self.post_transaction_ticks > 0 {
+            while MTIME.mtime() < self.ready_at {}
+        }
+    }
+
+    /// Records when the next transaction may assert CS again
+    pub(crate) fn transaction_done(&mut self) {
+        if self.post_transaction_ticks > 0 {
+            self.ready_at = MTIME.mtime() + self.post_transaction_ticks;
+        }
+    }
+
+    /// Runs `f` on the bus as a single transaction with CS asserted and
+    /// interrupts masked
+    fn with_bus<R>(&mut self, f: impl FnOnce(&mut SpiBus<SPI, PINS>) -> R) -> R
+    where
+        SPI: SpiX,
+        PINS: Pins<SPI>,
+        CS: PinCS<SPI>,
+    {
+        self.wait_ready();
+        let result = interrupt::free(|| {
+            let mut bus = self.bus.borrow_mut();
+
+            bus.configure(&self.config, Some(CS::CS_INDEX));
+
+            bus.start_frame();
+            let result = f(&mut bus);
+            bus.end_frame();
+
+            result
+        });
+        self.transaction_done();
+
+        result
+    }
 }
 
 impl<'bus, SPI, PINS, CS> SpiSharedDevice<'bus, SPI, PINS, CS>
@@ -31,9 +80,147 @@ where
             bus,
             cs,
             config: config.clone(),
+            post_transaction_ticks: config.post_transaction_ticks(),
+            ready_at: 0,
         }
     }
 
+    /// Reads `count` bytes, passing each received byte to `f` as it arrives
+    ///
+    /// Zeros are sent while reading. `f` runs inside the transfer loop, so a slow
+    /// callback stalls the bus between bytes; no more bytes are sent than the RX
+    /// FIFO can hold, so none are lost.
+    pub fn read_into<F>(&mut self, count: usize, f: F) -> Result<(), Error>
+    where
+        F: FnMut(u8),
+    {
+        self.with_bus(|bus| bus.read_into(count, f))
+    }
+
+    /// Writes `command`, clocks out `dummy` bytes and reads the response into
+    /// `read`, with CS held asserted throughout
+    ///
+    /// This matches the command/dummy cycles/data sequence of e.g. flash fast reads.
+    /// Dummy bytes are sent as zeros and the bytes received meanwhile are discarded.
+    pub fn write_dummy_read(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        read: &mut [u8],
+    ) -> Result<(), Error> {
+        self.with_bus(|bus| bus.write_dummy_read(command, dummy, read))
+    }
+
+    /// Like [write_dummy_read](Self::write_dummy_read), but reads the response
+    /// over the data lines of `protocol`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protocol` needs more data lines than the pins of the bus have.
+    pub fn write_dummy_read_wide(
+        &mut self,
+        command: &[u8],
+        dummy: u8,
+        protocol: PROTO_A,
+        read: &mut [u8],
+    ) -> Result<(), Error> {
+        self.with_bus(|bus| bus.write_dummy_read_wide(command, dummy, protocol, read))
+    }
+
+    /// Writes a 32-bit `word` for devices with frames wider than the controller's
+    ///
+    /// The word is sent as four 8-bit sub-frames, most significant byte first,
+    /// with CS held asserted across all of them.
+    pub fn write_u32_be(&mut self, word: u32) -> Result<(), Error> {
+        self.with_bus(|bus| bus.write_u32_be(word))
+    }
+
+    /// Transfers a 32-bit `word` for devices with frames wider than the controller's
+    ///
+    /// The word is sent as four 8-bit sub-frames, most significant byte first,
+    /// with CS held asserted across all of them. Returns the received word.
+    pub fn transfer_u32_be(&mut self, word: u32) -> Result<u32, Error> {
+        self.with_bus(|bus| bus.transfer_u32_be(word))
+    }
+
+    /// Writes 16-bit `words`, e.g. for displays and ADCs with 9 to 16-bit frames
+    ///
+    /// The controller frames are at most 8 bits long, so each word is sent as two
+    /// 8-bit sub-frames, most significant byte first, with CS held asserted across
+    /// all of them. Devices with shorter frames should ignore the leading bits.
+    pub fn write_u16_be(&mut self, words: &[u16]) -> Result<(), Error> {
+        self.with_bus(|bus| bus.write_u16_be(words))
+    }
+
+    /// Transfers 16-bit `words` in place, see [write_u16_be](Self::write_u16_be)
+    pub fn transfer_u16_be(&mut self, words: &mut [u16]) -> Result<(), Error> {
+        self.with_bus(|bus| bus.transfer_u16_be(words))
+    }
+
+    /// Writes `words` and checks that the bytes read back are the same,
+    /// e.g. with MOSI and MISO tied together.
+    ///
+    /// Returns the index of the first mismatching byte on failure. Bytes received
+    /// for unread `FullDuplex` sends are discarded first.
+    pub fn transfer_verify(&mut self, words: &[u8]) -> Result<(), usize> {
+        self.with_bus(|bus| bus.transfer_verify(words))
+    }
+
+    /// Transfers `write` while reading into `read`, giving up once `max_ticks`
+    /// mtime ticks have elapsed, see [SpiExclusiveDevice::transfer_with_timeout](super::SpiExclusiveDevice::transfer_with_timeout)
+    ///
+    /// Interrupts stay masked for up to `max_ticks`.
+    pub fn transfer_with_timeout(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+        max_ticks: u64,
+    ) -> Result<(), Error> {
+        self.with_bus(|bus| bus.transfer_with_timeout(read, write, max_ticks))
+    }
+
+    /// Executes `operations` as a single transaction without masking interrupts
+    ///
+    /// Unlike [Transactional::exec], the bus is only guarded by its `RefCell`
+    /// borrow, so interrupt handlers keep running during long transactions.
+    ///
+    /// **Re-entrancy contract:** any interrupt handler that starts a
+    /// transaction on the same [SharedBus] while this one is in progress
+    /// panics with a `BorrowMutError`. Only use this when no code that can
+    /// preempt the caller touches the same bus.
+    pub fn transaction_no_cs_free(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Error> {
+        self.wait_ready();
+        let result = {
+            let mut bus = self.bus.borrow_mut();
+
+            bus.configure(&self.config, Some(CS::CS_INDEX));
+
+            bus.start_frame();
+            let result = bus.exec(operations);
+            bus.end_frame();
+
+            result
+        };
+        self.transaction_done();
+
+        result
+    }
+
+    /// Changes the SPI frequency of this device, see [SpiConfig::set_frequency].
+    /// It is applied with the next transaction.
+    pub fn set_frequency(&mut self, freq: Hertz, clocks: &Clocks) -> Result<(), RateError> {
+        self.config.set_frequency(freq, clocks)
+    }
+
+    /// Recalculates the SPI clock divisor of this device after the core clock
+    /// frequency has changed. It is applied with the next transaction.
+    pub fn reclock(&mut self, clocks: &Clocks) -> Result<(), RateError> {
+        self.config.reclock(clocks)
+    }
+
     /// Releases the CS pin back
     pub fn release(self) -> CS {
         self.cs
@@ -46,9 +233,9 @@ where
     PINS: Pins<SPI>,
     CS: PinCS<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
-    fn read(&mut self) -> nb::Result<u8, Infallible> {
+    fn read(&mut self) -> nb::Result<u8, Error> {
         interrupt::free(|| {
             let mut bus = self.bus.borrow_mut();
 
@@ -58,7 +245,7 @@ where
         })
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
         interrupt::free(|| {
             let mut bus = self.bus.borrow_mut();
 
@@ -75,20 +262,25 @@ where
     PINS: Pins<SPI>,
     CS: PinCS<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
-        interrupt::free(move || {
-            let mut bus = self.bus.borrow_mut();
+        self.wait_ready();
+        let (bus, config) = (self.bus, &self.config);
+        let result = interrupt::free(move || {
+            let mut bus = bus.borrow_mut();
 
-            bus.configure(&self.config, Some(CS::CS_INDEX));
+            bus.configure(config, Some(CS::CS_INDEX));
 
             bus.start_frame();
             let result = bus.transfer(words);
             bus.end_frame();
 
             result
-        })
+        });
+        self.transaction_done();
+
+        result
     }
 }
 
@@ -98,20 +290,10 @@ where
     PINS: Pins<SPI>,
     CS: PinCS<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-        interrupt::free(|| {
-            let mut bus = self.bus.borrow_mut();
-
-            bus.configure(&self.config, Some(CS::CS_INDEX));
-
-            bus.start_frame();
-            let result = bus.write(words);
-            bus.end_frame();
-
-            result
-        })
+        self.with_bus(|bus| bus.write(words))
     }
 }
 
@@ -121,23 +303,13 @@ where
     PINS: Pins<SPI>,
     CS: PinCS<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
     fn write_iter<WI>(&mut self, words: WI) -> Result<(), Self::Error>
     where
         WI: IntoIterator<Item = u8>,
     {
-        interrupt::free(|| {
-            let mut bus = self.bus.borrow_mut();
-
-            bus.configure(&self.config, Some(CS::CS_INDEX));
-
-            bus.start_frame();
-            let result = bus.write_iter(words);
-            bus.end_frame();
-
-            result
-        })
+        self.with_bus(|bus| bus.write_iter(words))
     }
 }
 
@@ -147,19 +319,9 @@ where
     PINS: Pins<SPI>,
     CS: PinCS<SPI>,
 {
-    type Error = Infallible;
+    type Error = Error;
 
-    fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Infallible> {
-        interrupt::free(|| {
-            let mut bus = self.bus.borrow_mut();
-
-            bus.configure(&self.config, Some(CS::CS_INDEX));
-
-            bus.start_frame();
-            let result = bus.exec(operations);
-            bus.end_frame();
-
-            result
-        })
+    fn exec<'op>(&mut self, operations: &mut [Operation<'op, u8>]) -> Result<(), Error> {
+        self.with_bus(|bus| bus.exec(operations))
     }
 }
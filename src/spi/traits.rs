@@ -2,11 +2,53 @@
 use core::ops::Deref;
 use e310x::{qspi0, QSPI0, QSPI1, QSPI2};
 
+#[cfg(feature = "async")]
+use crate::waker::{WakerCell, WakerQueue};
+
+/// Number of async devices that can wait for a shared bus without polling
+#[cfg(feature = "async")]
+const BUS_WAITERS: usize = 8;
+
 #[doc(hidden)]
-pub trait SpiX: Deref<Target = qspi0::RegisterBlock> + private::Sealed {}
-impl SpiX for QSPI0 {}
-impl SpiX for QSPI1 {}
-impl SpiX for QSPI2 {}
+pub trait SpiX: Deref<Target = qspi0::RegisterBlock> + private::Sealed {
+    /// Number of hardware CS lines of the instance
+    const CS_COUNT: u32;
+    #[cfg(feature = "async")]
+    fn registers() -> &'static qspi0::RegisterBlock;
+    #[cfg(feature = "async")]
+    fn waker() -> &'static WakerCell;
+    #[cfg(feature = "async")]
+    fn bus_wakers() -> &'static WakerQueue<BUS_WAITERS>;
+}
+
+macro_rules! spix {
+    ($($SPI:ident: $cs_count:expr,)+) => {
+        $(
+            impl SpiX for $SPI {
+                const CS_COUNT: u32 = $cs_count;
+
+                #[cfg(feature = "async")]
+                fn registers() -> &'static qspi0::RegisterBlock {
+                    unsafe { &*$SPI::ptr() }
+                }
+
+                #[cfg(feature = "async")]
+                fn waker() -> &'static WakerCell {
+                    static WAKER: WakerCell = WakerCell::new();
+                    &WAKER
+                }
+
+                #[cfg(feature = "async")]
+                fn bus_wakers() -> &'static WakerQueue<BUS_WAITERS> {
+                    static WAKERS: WakerQueue<BUS_WAITERS> = WakerQueue::new();
+                    &WAKERS
+                }
+            }
+        )+
+    };
+}
+
+spix!(QSPI0: 1, QSPI1: 4, QSPI2: 1,);
 
 /// SPI pins - DO NOT IMPLEMENT THIS TRAIT
 ///
@@ -15,6 +57,9 @@ impl SpiX for QSPI2 {}
 pub trait Pins<SPI>: private::Sealed {
     #[doc(hidden)]
     const CS_INDEX: Option<u32>;
+    #[doc(hidden)]
+    /// Number of wired data lines, i.e. the widest usable protocol
+    const DATA_LINES: u8 = 1;
 }
 
 /// SPI pins without CS - DO NOT IMPLEMENT THIS TRAIT
@@ -34,6 +79,8 @@ pub trait PinCS<SPI>: private::Sealed {
 /* SPI0 pins */
 impl Pins<QSPI0> for () {
     const CS_INDEX: Option<u32> = Some(0);
+    // The dedicated flash pins include DQ2 and DQ3
+    const DATA_LINES: u8 = 4;
 }
 
 /* SPI1 pins */
@@ -70,6 +117,7 @@ mod spi1_impl {
 
     impl Pins<QSPI1> for (MOSI, MISO, SCK) {
         const CS_INDEX: Option<u32> = None;
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI1> for (MOSI, (), SCK) {
         const CS_INDEX: Option<u32> = None;
@@ -79,6 +127,7 @@ mod spi1_impl {
     }
     impl Pins<QSPI1> for (MOSI, MISO, SCK, CS0) {
         const CS_INDEX: Option<u32> = Some(0);
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI1> for (MOSI, (), SCK, CS0) {
         const CS_INDEX: Option<u32> = Some(0);
@@ -88,6 +137,7 @@ mod spi1_impl {
     }
     impl Pins<QSPI1> for (MOSI, MISO, SCK, CS1) {
         const CS_INDEX: Option<u32> = Some(1);
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI1> for (MOSI, (), SCK, CS1) {
         const CS_INDEX: Option<u32> = Some(1);
@@ -97,6 +147,7 @@ mod spi1_impl {
     }
     impl Pins<QSPI1> for (MOSI, MISO, SCK, CS2) {
         const CS_INDEX: Option<u32> = Some(2);
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI1> for (MOSI, (), SCK, CS2) {
         const CS_INDEX: Option<u32> = Some(2);
@@ -106,6 +157,7 @@ mod spi1_impl {
     }
     impl Pins<QSPI1> for (MOSI, MISO, SCK, CS3) {
         const CS_INDEX: Option<u32> = Some(3);
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI1> for (MOSI, (), SCK, CS3) {
         const CS_INDEX: Option<u32> = Some(3);
@@ -162,6 +214,7 @@ mod spi2_impl {
 
     impl Pins<QSPI2> for (MOSI, MISO, SCK) {
         const CS_INDEX: Option<u32> = None;
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI2> for (MOSI, (), SCK) {
         const CS_INDEX: Option<u32> = None;
@@ -171,6 +224,7 @@ mod spi2_impl {
     }
     impl Pins<QSPI2> for (MOSI, MISO, SCK, CS0) {
         const CS_INDEX: Option<u32> = Some(0);
+        const DATA_LINES: u8 = 2;
     }
     impl Pins<QSPI2> for (MOSI, (), SCK, CS0) {
         const CS_INDEX: Option<u32> = Some(0);
@@ -1,6 +1,16 @@
 //! Stdout
+//!
+//! [Stdout] and [TimeoutStdout] wrap a serial port to implement [Write]. To use
+//! the [sprint!](crate::sprint) and [sprintln!](crate::sprintln) macros, route
+//! the global stdout to any `'static` [Write] sink with [set_stdout], e.g. a
+//! [Stdout] around UART1, a display driver or a semihosting channel.
+use core::cell::UnsafeCell;
 pub use core::fmt::Write;
 use nb::block;
+use riscv::interrupt;
+
+use crate::core::clint::MTIME;
+use crate::time::{duration_to_ticks, Microseconds};
 
 /// Stdout implements the core::fmt::Write trait for hal::serial::Write
 /// implementations.
@@ -31,3 +41,121 @@ where
         Ok(())
     }
 }
+
+/// Stdout that gives up with `fmt::Error` instead of blocking forever when the
+/// serial port doesn't accept a byte within a timeout
+///
+/// A disconnected or stalled UART then can't hang logging calls. The timeout
+/// applies to every byte and is measured with `mtime`.
+pub struct TimeoutStdout<'p, T> {
+    serial: &'p mut T,
+    timeout_ticks: u64,
+}
+
+impl<'p, T> TimeoutStdout<'p, T>
+where
+    T: embedded_hal::serial::Write<u8>,
+{
+    /// Wraps `serial`, waiting at most `timeout` for each byte to be accepted
+    pub fn new(serial: &'p mut T, timeout: Microseconds) -> Self {
+        Self {
+            serial,
+            timeout_ticks: duration_to_ticks(timeout),
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> ::core::fmt::Result {
+        let deadline = MTIME.mtime() + self.timeout_ticks;
+        loop {
+            match self.serial.write(byte) {
+                Ok(()) => return Ok(()),
+                Err(nb::Error::WouldBlock) if MTIME.mtime() < deadline => {}
+                Err(_) => return Err(::core::fmt::Error),
+            }
+        }
+    }
+}
+
+impl<'p, T> Write for TimeoutStdout<'p, T>
+where
+    T: embedded_hal::serial::Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
+        for byte in s.as_bytes() {
+            if *byte == b'\n' {
+                self.write_byte(b'\r')?;
+            }
+
+            self.write_byte(*byte)?;
+        }
+        Ok(())
+    }
+}
+
+type Sink = &'static mut (dyn Write + Send);
+
+struct GlobalStdout {
+    sink: UnsafeCell<Option<Sink>>,
+}
+
+// The inner sink is only accessed within critical sections
+unsafe impl Sync for GlobalStdout {}
+
+static STDOUT: GlobalStdout = GlobalStdout {
+    sink: UnsafeCell::new(None),
+};
+
+/// Routes the global stdout to `sink`, returning the previous sink if any
+pub fn set_stdout(sink: Sink) -> Option<Sink> {
+    interrupt::free(move || unsafe { (*STDOUT.sink.get()).replace(sink) })
+}
+
+/// Removes and returns the global stdout sink, if any
+pub fn take_stdout() -> Option<Sink> {
+    interrupt::free(|| unsafe { (*STDOUT.sink.get()).take() })
+}
+
+/// Writes `args` to the global stdout
+///
+/// Output is dropped while no sink is set. The whole message is written within
+/// a critical section, so messages from interrupt handlers don't interleave.
+/// The sink is taken out of the global stdout while writing, so prints nested
+/// in a `Display` impl or in the sink itself are dropped too.
+pub fn print(args: core::fmt::Arguments) -> ::core::fmt::Result {
+    interrupt::free(|| {
+        let sink = match unsafe { (*STDOUT.sink.get()).take() } {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+        let res = sink.write_fmt(args);
+        // Keep a sink set by a nested set_stdout call
+        let slot = unsafe { &mut *STDOUT.sink.get() };
+        if slot.is_none() {
+            *slot = Some(sink);
+        }
+        res
+    })
+}
+
+/// Prints to the global stdout set with [set_stdout](crate::stdout::set_stdout)
+#[macro_export]
+macro_rules! sprint {
+    ($($arg:tt)*) => {
+        { let _ = $crate::stdout::print(format_args!($($arg)*)); }
+    };
+}
+
+/// Prints to the global stdout set with [set_stdout](crate::stdout::set_stdout),
+/// with a newline
+#[macro_export]
+macro_rules! sprintln {
+    () => {
+        $crate::sprint!("\n")
+    };
+    ($fmt:expr) => {
+        $crate::sprint!(concat!($fmt, "\n"))
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::sprint!(concat!($fmt, "\n"), $($arg)*)
+    };
+}
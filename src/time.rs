@@ -1,21 +1,91 @@
 //! Time units
 
 /// Bits per second
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Bps(pub u32);
 
 /// Hertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Hertz(pub u32);
 
 /// KiloHertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct KiloHertz(pub u32);
 
 /// MegaHertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct MegaHertz(pub u32);
 
+/// Microseconds
+#[derive(Clone, Copy, Debug)]
+pub struct Microseconds(pub u32);
+
+/// Error returned for a baud rate or frequency that a peripheral clock
+/// divisor can't produce from the current clocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateError {
+    /// The rate is above the highest achievable one
+    TooFast,
+    /// The rate is below the lowest achievable one
+    TooSlow,
+}
+
+/// Rate of the machine timer (mtime) in ticks per second
+pub const MTIME_TICKS_PER_SECOND: u64 = 32_768;
+
+/// Converts `value` units of `1 / units_per_second` seconds to ticks of a timer
+/// running at `ticks_per_second`, rounding up so that waiting for the returned
+/// number of ticks lasts at least the given time
+///
+/// ```
+/// use e310x_hal::time::{units_to_ticks, MTIME_TICKS_PER_SECOND};
+///
+/// // 1 ns .. 30517 ns are one mtime tick
+/// assert_eq!(units_to_ticks(1, 1_000_000_000, MTIME_TICKS_PER_SECOND), 1);
+/// assert_eq!(units_to_ticks(30_518, 1_000_000_000, MTIME_TICKS_PER_SECOND), 2);
+/// assert_eq!(units_to_ticks(u32::MAX as u64, 1_000, MTIME_TICKS_PER_SECOND), 140_737_488_323);
+/// ```
+pub fn units_to_ticks(value: u64, units_per_second: u64, ticks_per_second: u64) -> u64 {
+    let num = value.saturating_mul(ticks_per_second);
+    num / units_per_second + (num % units_per_second != 0) as u64
+}
+
+/// Converts a duration to mtime ticks, rounding up so that waiting for the
+/// returned number of ticks lasts at least `duration`
+///
+/// ```
+/// use e310x_hal::time::{duration_to_ticks, Microseconds, MTIME_TICKS_PER_SECOND};
+///
+/// assert_eq!(duration_to_ticks(Microseconds(0)), 0);
+/// assert_eq!(duration_to_ticks(Microseconds(1)), 1);
+/// assert_eq!(duration_to_ticks(Microseconds(30)), 1);
+/// assert_eq!(duration_to_ticks(Microseconds(31)), 2);
+/// // 500 µs are 16.384 ticks, rounded up to 17
+/// assert_eq!(duration_to_ticks(Microseconds(500)), 17);
+/// assert_eq!(duration_to_ticks(Microseconds(1_000_000)), MTIME_TICKS_PER_SECOND);
+/// assert_eq!(duration_to_ticks(Microseconds(u32::MAX)), 140_737_489);
+/// ```
+pub fn duration_to_ticks(duration: Microseconds) -> u64 {
+    units_to_ticks(duration.0 as u64, 1_000_000, MTIME_TICKS_PER_SECOND)
+}
+
+/// Converts mtime ticks to a duration, rounding down and saturating at `u32::MAX` µs
+///
+/// ```
+/// use e310x_hal::time::ticks_to_duration;
+///
+/// assert_eq!(ticks_to_duration(0).0, 0);
+/// assert_eq!(ticks_to_duration(1).0, 30);
+/// assert_eq!(ticks_to_duration(32_768).0, 1_000_000);
+/// assert_eq!(ticks_to_duration(140_737_488).0, 4_294_967_285);
+/// assert_eq!(ticks_to_duration(140_737_489).0, u32::MAX);
+/// assert_eq!(ticks_to_duration(u64::MAX).0, u32::MAX);
+/// ```
+pub fn ticks_to_duration(ticks: u64) -> Microseconds {
+    let us = ticks.saturating_mul(1_000_000) / MTIME_TICKS_PER_SECOND;
+    Microseconds(us.min(u32::MAX as u64) as u32)
+}
+
 /// Extension trait that adds convenience methods to the `u32` type
 pub trait U32Ext {
     /// Wrap in `Bps`
@@ -29,6 +99,9 @@ pub trait U32Ext {
 
     /// Wrap in `MegaHertz`
     fn mhz(self) -> MegaHertz;
+
+    /// Wrap in `Microseconds`
+    fn us(self) -> Microseconds;
 }
 
 impl U32Ext for u32 {
@@ -47,6 +120,10 @@ impl U32Ext for u32 {
     fn mhz(self) -> MegaHertz {
         MegaHertz(self)
     }
+
+    fn us(self) -> Microseconds {
+        Microseconds(self)
+    }
 }
 
 impl Into<Hertz> for KiloHertz {
@@ -66,3 +143,19 @@ impl Into<KiloHertz> for MegaHertz {
         KiloHertz(self.0 * 1_000)
     }
 }
+
+/// Conversions from [`fugit`] rates. Rates are converted to a whole number of
+/// Hertz, sub-Hertz fractions are truncated.
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Rate<u32, NOM, DENOM>> for Hertz {
+    fn from(rate: fugit::Rate<u32, NOM, DENOM>) -> Self {
+        Hertz(rate.to_Hz())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Hertz> for fugit::HertzU32 {
+    fn from(hz: Hertz) -> Self {
+        fugit::HertzU32::from_raw(hz.0)
+    }
+}
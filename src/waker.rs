@@ -0,0 +1,103 @@
+//! Waker storage shared between futures and interrupt handlers
+
+use core::cell::UnsafeCell;
+use core::task::Waker;
+
+use riscv::interrupt;
+
+/// Holds the waker of the task waiting on a peripheral interrupt
+pub struct WakerCell {
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// The inner waker is only accessed within critical sections
+unsafe impl Sync for WakerCell {}
+
+impl WakerCell {
+    /// Creates an empty waker cell
+    pub const fn new() -> Self {
+        Self {
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Registers `waker` to be woken by the next call to [wake](Self::wake)
+    ///
+    /// A different waker already registered is woken right away, so that its task
+    /// polls again and registers itself anew instead of never being woken.
+    pub fn register(&self, waker: &Waker) {
+        let displaced = interrupt::free(|| {
+            let slot = unsafe { &mut *self.waker.get() };
+            match slot {
+                Some(w) if w.will_wake(waker) => None,
+                _ => slot.replace(waker.clone()),
+            }
+        });
+        if let Some(displaced) = displaced {
+            displaced.wake();
+        }
+    }
+
+    /// Wakes the registered task, if any
+    pub fn wake(&self) {
+        let waker = interrupt::free(|| unsafe { (*self.waker.get()).take() });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Holds the wakers of the tasks waiting for a shared resource to be released
+///
+/// Up to `N` tasks are tracked, all of them are woken together by [wake_all](Self::wake_all).
+pub struct WakerQueue<const N: usize> {
+    wakers: UnsafeCell<[Option<Waker>; N]>,
+}
+
+// The inner wakers are only accessed within critical sections
+unsafe impl<const N: usize> Sync for WakerQueue<N> {}
+
+impl<const N: usize> WakerQueue<N> {
+    const EMPTY: Option<Waker> = None;
+
+    /// Creates an empty waker queue
+    pub const fn new() -> Self {
+        Self {
+            wakers: UnsafeCell::new([Self::EMPTY; N]),
+        }
+    }
+
+    /// Adds `waker` to the queue, unless it is queued already
+    ///
+    /// Wakers already in the queue are left untouched. If the queue is full,
+    /// `waker` is woken right away so that its task retries on its next poll.
+    pub fn register(&self, waker: &Waker) {
+        let queued = interrupt::free(|| {
+            let slots = unsafe { &mut *self.wakers.get() };
+            if slots.iter().flatten().any(|w| w.will_wake(waker)) {
+                return true;
+            }
+            match slots.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => {
+                    *slot = Some(waker.clone());
+                    true
+                }
+                None => false,
+            }
+        });
+        if !queued {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Wakes and removes all queued tasks
+    pub fn wake_all(&self) {
+        let wakers = interrupt::free(|| unsafe {
+            core::mem::replace(&mut *self.wakers.get(), [Self::EMPTY; N])
+        });
+        // Edition 2018 resolves `wakers.into_iter()` to iterating by reference
+        for waker in IntoIterator::into_iter(wakers).flatten() {
+            waker.wake();
+        }
+    }
+}
@@ -1,6 +1,25 @@
 //! Watchdog
 #![allow(missing_docs)]
-use e310x::WDOG;
+use e310x::{wdog, WDOG};
+
+use crate::{
+    clock::Clocks,
+    core::plic::Plic,
+    time::{units_to_ticks, Microseconds},
+};
+
+/// Value of `wdogkey` that unlocks the next write to a watchdog register
+pub(crate) const WDOG_KEY: u32 = 0x51F15E;
+
+/// Unlocks the watchdog registers and runs `write`
+///
+/// Every write to a watchdog register must directly follow the key, so all of
+/// them go through here. `write` must write a single register.
+pub(crate) fn write_unlocked<F: FnOnce(&wdog::RegisterBlock)>(write: F) {
+    let wdog = unsafe { &*WDOG::ptr() };
+    wdog.wdogkey.write(|w| unsafe { w.bits(WDOG_KEY) });
+    write(wdog);
+}
 
 pub trait WdogExt {
     fn configure(self) -> WdogCfg;
@@ -55,9 +74,8 @@ impl WdogCfg {
     }
 
     pub fn freeze(self) -> Wdog {
-        unsafe {
-            (*WDOG::ptr()).wdogkey.write(|w| w.bits(0x51F15E));
-            (*WDOG::ptr()).wdogcfg.write(|w| {
+        write_unlocked(|wdog| {
+            wdog.wdogcfg.write(|w| unsafe {
                 w.scale()
                     .bits(self.scale)
                     .rsten()
@@ -68,8 +86,8 @@ impl WdogCfg {
                     .bit(self.enable)
                     .encoreawake()
                     .bit(self.awake)
-            });
-        }
+            })
+        });
         Wdog { _0: () }
     }
 }
@@ -79,18 +97,12 @@ pub struct Wdog {
 }
 
 impl Wdog {
-    #[inline]
-    fn unlock(&mut self) {
-        unsafe { (*WDOG::ptr()).wdogkey.write(|w| w.bits(0x51F15E)) };
-    }
-
     pub fn is_pending(&self) -> bool {
         unsafe { (*WDOG::ptr()).wdogcfg.read().cmpip().bit() }
     }
 
     pub fn feed(&mut self) {
-        self.unlock();
-        unsafe { (*WDOG::ptr()).wdogfeed.write(|w| w.bits(0xD09F00D)) };
+        write_unlocked(|wdog| wdog.wdogfeed.write(|w| unsafe { w.bits(0xD09F00D) }));
     }
 
     pub fn cmp(&self) -> u16 {
@@ -98,6 +110,74 @@ impl Wdog {
     }
 
     pub fn set_cmp(&mut self, value: u16) {
-        unsafe { (*WDOG::ptr()).wdogcmp.write(|w| w.value().bits(value)) };
+        write_unlocked(|wdog| wdog.wdogcmp.write(|w| unsafe { w.value().bits(value) }));
+    }
+
+    /// Clears the pending comparator interrupt
+    pub fn clear_pending(&mut self) {
+        write_unlocked(|wdog| wdog.wdogcfg.modify(|_, w| w.cmpip().clear_bit()));
+    }
+
+    /// Routes the comparator interrupt to the machine external interrupt through the PLIC
+    ///
+    /// For a two-stage watchdog, start it without reset. In the first interrupt, log
+    /// the state, then [feed](Self::feed), [clear_pending](Self::clear_pending) and
+    /// [set_reset(true)](Self::set_reset): the next expiry resets the chip.
+    pub fn listen(&mut self, plic: &mut Plic) {
+        plic.wdog.enable();
+    }
+
+    /// Stops routing the comparator interrupt to the PLIC
+    pub fn unlisten(&mut self, plic: &mut Plic) {
+        plic.wdog.disable();
+    }
+
+    /// Selects whether the timeout resets the chip or only raises the interrupt
+    pub fn set_reset(&mut self, reset: bool) {
+        write_unlocked(|wdog| wdog.wdogcfg.modify(|_, w| w.rsten().bit(reset)));
+    }
+
+    /// Starts the watchdog with a timeout of at least `timeout`, counting
+    /// `lfclk` cycles
+    ///
+    /// The smallest scale that fits the timeout in the comparator is used, so
+    /// the timeout is rounded up to a multiple of `2^scale` cycles. The watchdog
+    /// is fed, and then counts continuously, also while the core sleeps. Timeouts
+    /// shorter than one cycle are raised to one cycle, as a zero comparator would
+    /// fire at once.
+    ///
+    /// `rsten` is left as it is, so whether the timeout resets the chip or only
+    /// raises the interrupt stays as configured, see [set_reset](Self::set_reset).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout` exceeds `0xffff << 15` `lfclk` cycles, about 65 s at 32.768 kHz.
+    pub fn start(&mut self, timeout: Microseconds, clocks: &Clocks) {
+        let cycles = units_to_ticks(timeout.0 as u64, 1_000_000, clocks.lfclk().0 as u64).max(1);
+        let scale = (0..=15u8)
+            .find(|scale| (cycles + (1 << scale) - 1) >> scale <= 0xffff)
+            .expect("watchdog timeout too long");
+        let cmp = ((cycles + (1 << scale) - 1) >> scale) as u16;
+
+        self.set_cmp(cmp);
+        self.feed();
+        write_unlocked(|wdog| {
+            wdog.wdogcfg.modify(|_, w| unsafe {
+                w.scale()
+                    .bits(scale)
+                    .zerocmp()
+                    .clear_bit()
+                    .enalways()
+                    .set_bit()
+            })
+        });
+    }
+
+    /// Stops the watchdog counter
+    pub fn disable(&mut self) {
+        write_unlocked(|wdog| {
+            wdog.wdogcfg
+                .modify(|_, w| w.enalways().clear_bit().encoreawake().clear_bit())
+        });
     }
 }